@@ -0,0 +1,49 @@
+// Writes the seed corpus of every fuzz target into corpus/<target>, run with `cargo run --example seed_corpus` from the fuzz directory
+use std::fs;
+
+use Lab5::Container::{encode_stream, CODEC_HUFFMAN, CODEC_LZW};
+use Lab5::TransformationMethods::{transform_pipeline, BWT, MTF, TRANSFORM_BLOCK_SIZE};
+use Lab5_fuzz::checksum_settings;
+
+fn samples() -> Vec<Vec<u8>> {
+    vec![
+        Vec::new(),
+        b"a".to_vec(),
+        b"banana".to_vec(),
+        b"mississippi river, mississippi state".to_vec(),
+        vec![0; 300],
+        (0..=255).collect(),
+        (0..TRANSFORM_BLOCK_SIZE + 10).map(|i| (i * i % 251) as u8).collect(),
+    ]
+}
+
+fn write_corpus(target: &str, inputs: Vec<Vec<u8>>) -> std::io::Result<()> {
+    let dir = format!("corpus/{}", target);
+    fs::create_dir_all(&dir)?;
+    for (input_id, input) in inputs.iter().enumerate() {
+        fs::write(format!("{}/seed-{:02}", dir, input_id), input)?;
+    }
+    Ok(())
+}
+
+fn main() -> std::io::Result<()> {
+    let encoded = |codec_id: u8| -> Vec<Vec<u8>> {
+        samples().iter().enumerate().flat_map(|(sample_id, sample)| [0, 1, 7, 11].map(|transform_id| {
+            let pipeline = transform_pipeline(transform_id).unwrap();
+            encode_stream(&mut sample.as_slice(), Vec::new(), codec_id, &pipeline, checksum_settings(sample_id as u8)).unwrap()
+        })).collect()
+    };
+    let blocks = || samples().into_iter().map(|sample| sample[..sample.len().min(TRANSFORM_BLOCK_SIZE)].to_vec());
+    let with_prefix = |prefix: &[u8]| -> Vec<Vec<u8>> {
+        samples().iter().map(|sample| [prefix, sample].concat()).collect()
+    };
+
+    write_corpus("huffman_decode", encoded(CODEC_HUFFMAN))?;
+    write_corpus("lzw_decode", encoded(CODEC_LZW))?;
+    write_corpus("inverse_bwt", blocks().map(|block| BWT(&block).unwrap()).collect())?;
+    write_corpus("inverse_mtf", blocks().map(|block| MTF(&block)).collect())?;
+    write_corpus("read_bit_sequence", with_prefix(&[3, 13]))?;
+    write_corpus("codec_round_trip", (0..4).flat_map(|selector| with_prefix(&[selector * 5, selector])).collect())?;
+    write_corpus("transform_round_trip", (0..20).flat_map(|transform_id| with_prefix(&[transform_id])).collect())?;
+    Ok(())
+}
@@ -0,0 +1,20 @@
+#![no_main]
+use std::io::Read;
+
+use libfuzzer_sys::fuzz_target;
+
+use Lab5::Container::{encode_stream, open_decoder, CODEC_HUFFMAN, CODEC_LZW};
+use Lab5_fuzz::{checksum_settings, split_pipeline};
+
+// Input: transform preset | codec and checksum selector | data
+fuzz_target!(|data: &[u8]| {
+    let Some((pipeline, data)) = split_pipeline(data) else { return };
+    let Some((&selector, data)) = data.split_first() else { return };
+    let codec_id = if selector & 1 == 0 { CODEC_HUFFMAN } else { CODEC_LZW };
+
+    let compressed = encode_stream(&mut &data[..], Vec::new(), codec_id, &pipeline, checksum_settings(selector >> 1)).unwrap();
+
+    let mut restored = Vec::new();
+    open_decoder(compressed.as_slice()).unwrap().read_to_end(&mut restored).unwrap();
+    assert_eq!(restored, data);
+});
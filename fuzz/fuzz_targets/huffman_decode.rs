@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use Lab5::Huffman::HuffmanDecoder;
+use Lab5_fuzz::{drain, MAX_FUZZ_OUTPUT_SIZE};
+
+fuzz_target!(|data: &[u8]| {
+    drain(HuffmanDecoder::with_output_limit(data, MAX_FUZZ_OUTPUT_SIZE));
+});
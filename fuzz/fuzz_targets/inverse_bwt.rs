@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use Lab5::TransformationMethods::{inverse_BWT, TRANSFORM_BLOCK_SIZE};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(restored) = inverse_BWT(data) {
+        assert!(restored.len() <= TRANSFORM_BLOCK_SIZE);
+    }
+});
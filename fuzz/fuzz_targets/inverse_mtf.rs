@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use Lab5::TransformationMethods::{inverse_MTF, MTF};
+
+// Every byte string is a valid MTF output, so the inverse has to be exact
+fuzz_target!(|data: &[u8]| {
+    assert_eq!(MTF(&inverse_MTF(data)), data);
+});
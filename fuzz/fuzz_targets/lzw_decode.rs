@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use Lab5::LZWCoderEnhanced::LZWDecoder;
+use Lab5_fuzz::{drain, MAX_FUZZ_OUTPUT_SIZE};
+
+// In-memory counterpart of LZWCoderEnhanced::decode_file
+fuzz_target!(|data: &[u8]| {
+    drain(LZWDecoder::with_output_limit(data, MAX_FUZZ_OUTPUT_SIZE));
+});
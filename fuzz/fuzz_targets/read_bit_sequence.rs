@@ -0,0 +1,27 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use Lab5::BitStream::{BitOrder, BitReader};
+
+// First two bytes are read lengths in bits, the rest is the stream
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let (lengths, stream) = data.split_at(2);
+    let bit_order = if lengths[0] & 1 == 0 { BitOrder::LSBFirst } else { BitOrder::MSBFirst };
+
+    let mut reader = BitReader::with_bit_order(stream, bit_order);
+    let mut bits_left = stream.len() * 8;
+    for &length in lengths.iter().cycle().take(stream.len() + 2) {
+        let size = length as usize;
+        let bits = match reader.read_bit_sequence(size) {
+            Ok(bits) => bits,
+            Err(_) => return,
+        };
+
+        let bits_read = size.min(bits_left);
+        assert_eq!(bits.len(), bits_read.div_ceil(8));
+        bits_left -= bits_read;
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use Lab5::TransformationMethods::{perform_inverse_transform, perform_transform, TRANSFORM_BLOCK_SIZE};
+use Lab5_fuzz::split_pipeline;
+
+// Input: transform preset | one block of data
+fuzz_target!(|data: &[u8]| {
+    let Some((pipeline, data)) = split_pipeline(data) else { return };
+    let block = &data[..data.len().min(TRANSFORM_BLOCK_SIZE)];
+
+    let transformed = perform_transform(block, &pipeline, 0).unwrap();
+    assert_eq!(perform_inverse_transform(&transformed, &pipeline, 0).unwrap(), block);
+});
@@ -0,0 +1,35 @@
+// Fuzz targets for decoders, inverse transforms and round trips, run from this directory with
+// `cargo +nightly fuzz run <target> corpus/<target>`, the seed corpus is made by `cargo run --example seed_corpus`.
+#![allow(non_snake_case)]
+
+use std::io::Read;
+
+use Lab5::Checksum::{ChecksumKind, ChecksumSettings};
+use Lab5::Error::Error;
+use Lab5::TransformationMethods::{transform_pipeline, TransformStage, TRANSFORM_PRESET_COUNT};
+
+// Decoders never restore more than this, larger streams are rejected before decoding (1MB)
+pub const MAX_FUZZ_OUTPUT_SIZE: u64 = 1 << 20;
+
+// Reads a decoder to the end, errors are expected outcomes for arbitrary input
+pub fn drain<R: Read>(decoder: Result<R, Error>) {
+    if let Ok(mut decoder) = decoder {
+        let mut restored = Vec::new();
+        let _ = decoder.read_to_end(&mut restored);
+        assert!(restored.len() as u64 <= MAX_FUZZ_OUTPUT_SIZE);
+    }
+}
+
+// First input byte picks the transform preset, the rest is the data
+pub fn split_pipeline(data: &[u8]) -> Option<(Vec<TransformStage>, &[u8])> {
+    let (&selector, data) = data.split_first()?;
+    Some((transform_pipeline(selector % TRANSFORM_PRESET_COUNT).unwrap(), data))
+}
+
+pub fn checksum_settings(selector: u8) -> ChecksumSettings {
+    match selector % 3 {
+        0 => ChecksumSettings::NONE,
+        1 => ChecksumSettings::new(ChecksumKind::CRC32, false),
+        _ => ChecksumSettings::new(ChecksumKind::XXH64, true),
+    }
+}
@@ -0,0 +1,750 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::Checksum::ChecksumSettings;
+use crate::Container;
+use crate::Error::{Error, Result};
+use crate::SpillBuffer::SpillBuffer;
+use crate::TransformationMethods::TransformStage;
+
+pub const ARCHIVE_MAGIC: [u8; 4] = *b"BSLA";
+pub const ARCHIVE_VERSION: u8 = 1;
+
+const FLAG_SOLID: u8 = 1;
+
+// magic (4) | version (1) | flags (1), solid archives continue with entry count (u32)
+const ARCHIVE_HEADER_SIZE: u64 = 6;
+
+// Codec and transforms used for compressing archive entries
+#[derive(Clone, Debug)]
+pub struct ArchiveSettings {
+    pub codec_id: u8,
+    pub pipeline: Vec<TransformStage>,
+    pub checksum: ChecksumSettings,
+    pub solid: bool,    // Compress all files as one stream instead of one stream per file
+}
+
+// Entry record: path length (u16) | path | is_dir (1) | mode (u32) | mtime (i64) | size (u64) | compressed size (u64)
+// In non-solid archives compressed file data follows every file record
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub path: String,           // Relative path with '/' separators
+    pub is_dir: bool,
+    pub mode: u32,              // Unix permission bits (only read-only flag on other platforms)
+    pub mtime: i64,             // Seconds since UNIX epoch
+    pub size: u64,
+    pub compressed_size: u64,   // Always 0 for directories and solid archives
+}
+
+impl ArchiveEntry {
+    fn record_size(&self) -> u64 {
+        2 + self.path.len() as u64 + 29
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        let path_len = u16::try_from(self.path.len()).map_err(|_| {
+            Error::invalid_parameter(format!("Path of {} bytes is longer than the limit of {}: {}", self.path.len(), u16::MAX, self.path))
+        })?;
+        writer.write_all(&path_len.to_le_bytes())?;
+        writer.write_all(self.path.as_bytes())?;
+        writer.write_all(&[self.is_dir as u8])?;
+        writer.write_all(&self.mode.to_le_bytes())?;
+        writer.write_all(&self.mtime.to_le_bytes())?;
+        writer.write_all(&self.size.to_le_bytes())?;
+        writer.write_all(&self.compressed_size.to_le_bytes())
+    }
+
+    // Returns None if there are no more entries, offset is the position of the record in the archive
+    fn read_from<R: Read>(reader: &mut R, offset: u64) -> Result<Option<Self>> {
+        let mut path_len = [0u8; 2];
+        if reader.read(&mut path_len[..1])? == 0 {
+            return Ok(None);
+        }
+        reader.read_exact(&mut path_len[1..]).map_err(|err| Error::from(err).truncated_at(offset))?;
+
+        let mut path = vec![0u8; u16::from_le_bytes(path_len) as usize];
+        reader.read_exact(&mut path).map_err(|err| Error::from(err).truncated_at(offset))?;
+        let path = String::from_utf8(path).map_err(|_| Error::corrupt(offset + 2, "Archive entry path is not UTF-8"))?;
+        if !is_safe_path(&path) {
+            return Err(Error::corrupt(offset + 2, format!("Unsafe path in archive: {}", path)));
+        }
+
+        let mut fields = [0u8; 29];
+        reader.read_exact(&mut fields).map_err(|err| Error::from(err).truncated_at(offset))?;
+
+        // Compressed data is skipped with a relative seek
+        let compressed_size = u64::from_le_bytes(fields[21..29].try_into().unwrap());
+        if compressed_size > i64::MAX as u64 {
+            return Err(Error::corrupt(offset, format!("Invalid compressed size of archive entry {}", path)));
+        }
+
+        Ok(Some(ArchiveEntry {
+            path,
+            is_dir: fields[0] != 0,
+            mode: u32::from_le_bytes(fields[1..5].try_into().unwrap()),
+            mtime: i64::from_le_bytes(fields[5..13].try_into().unwrap()),
+            size: u64::from_le_bytes(fields[13..21].try_into().unwrap()),
+            compressed_size,
+        }))
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() { 0o444 } else { 0o644 }
+}
+
+#[cfg(unix)]
+fn set_file_mode(path: &Path, mode: u32) -> Result<(), std::io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_file_mode(path: &Path, mode: u32) -> Result<(), std::io::Error> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    fs::set_permissions(path, permissions)
+}
+
+fn set_mtime(path: &Path, mtime: i64) -> Result<()> {
+    let time = if mtime >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(mtime as u64))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(mtime.unsigned_abs()))
+    };
+    let time = time.ok_or_else(|| Error::invalid_parameter(format!("Modification time out of range: {}", mtime)))?;
+
+    let file = if path.is_dir() { File::open(path)? } else { OpenOptions::new().write(true).open(path)? };
+    Ok(file.set_modified(time)?)
+}
+
+fn entry_from_metadata(path: String, metadata: &fs::Metadata) -> ArchiveEntry {
+    let mtime = match metadata.modified().unwrap_or(UNIX_EPOCH).duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => since_epoch.as_secs() as i64,
+        Err(before_epoch) => -(before_epoch.duration().as_secs() as i64),
+    };
+
+    ArchiveEntry {
+        path,
+        is_dir: metadata.is_dir(),
+        mode: file_mode(metadata),
+        mtime,
+        size: if metadata.is_dir() { 0 } else { metadata.len() },
+        compressed_size: 0,
+    }
+}
+
+// Name an input is stored under, paths like "." or "dir/.." are named after the directory they stand for
+pub fn input_name(input: &str) -> Result<String> {
+    let path = Path::new(input);
+    let name = match path.file_name() {
+        Some(name) => name.to_owned(),
+        None => fs::canonicalize(path)?.file_name()
+                    .ok_or_else(|| Error::invalid_parameter(format!("Cannot archive path without a name: {}", input)))?
+                    .to_owned(),
+    };
+    Ok(name.to_string_lossy().to_string())
+}
+
+// Collects entries for input paths, directories are walked recursively and stored under their own name.
+// Input paths are followed if they are symbolic links, links met inside directories are skipped
+// (following them could leave the tree or never end), as are other special files. The archive itself
+// is skipped when it lies inside an input directory.
+fn collect_entries(inputs: &[&str], archive_path: &str) -> Result<Vec<(PathBuf, ArchiveEntry)>> {
+    let mut entries = Vec::new();
+    let mut stack: Vec<(PathBuf, String, bool)> = Vec::new();
+    let archive = fs::canonicalize(archive_path).ok();
+
+    for input in inputs.iter().rev() {
+        stack.push((PathBuf::from(input), input_name(input)?, true));
+    }
+
+    while let Some((path, archive_path, is_input)) = stack.pop() {
+        let metadata = if is_input { fs::metadata(&path)? } else { fs::symlink_metadata(&path)? };
+        if !metadata.is_dir() && !metadata.is_file() {
+            continue;
+        }
+        if metadata.is_file() && archive.is_some() && fs::canonicalize(&path).ok() == archive {
+            continue;
+        }
+
+        if metadata.is_dir() {
+            let mut children: Vec<PathBuf> = fs::read_dir(&path)?.map(|child| child.map(|c| c.path()))
+                                                                   .collect::<Result<_, _>>()?;
+            children.sort();
+
+            for child in children.into_iter().rev() {
+                let child_name = child.file_name().unwrap().to_string_lossy().to_string();
+                stack.push((child, format!("{}/{}", archive_path, child_name), false));
+            }
+        }
+
+        entries.push((path, entry_from_metadata(archive_path, &metadata)));
+    }
+
+    Ok(entries)
+}
+
+// Only plain relative paths, so extraction stays inside the output directory
+fn is_safe_path(entry_path: &str) -> bool {
+    !entry_path.is_empty() && Path::new(entry_path).components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+fn safe_output_path(output_dir: &Path, entry_path: &str) -> Result<PathBuf> {
+    if !is_safe_path(entry_path) {
+        return Err(Error::invalid_parameter(format!("Unsafe path in archive: {}", entry_path)));
+    }
+
+    Ok(output_dir.join(entry_path))
+}
+
+fn copy_exact<R: Read, W: Write>(reader: &mut R, writer: &mut W, size: u64) -> Result<(), std::io::Error> {
+    let copied = std::io::copy(&mut reader.take(size), writer)?;
+    if copied != size {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+fn file_changed(source: &Path) -> Error {
+    Error::Io(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, format!("File {} changed while archiving", source.display())))
+}
+
+fn read_archive_header<R: Read>(reader: &mut R) -> Result<u8> {
+    let mut header = [0u8; ARCHIVE_HEADER_SIZE as usize];
+    reader.read_exact(&mut header).map_err(|err| Error::from(err).truncated_at(0))?;
+
+    if header[0..4] != ARCHIVE_MAGIC {
+        return Err(Error::corrupt(0, "Not an archive: invalid magic bytes"));
+    }
+    if header[4] != ARCHIVE_VERSION {
+        return Err(Error::UnsupportedVersion { format: "archive", version: header[4] });
+    }
+
+    Ok(header[5])
+}
+
+// Entry table of a solid archive (after the header), returns entries and the position of the compressed stream
+fn read_solid_table<R: Read>(reader: &mut R) -> Result<(Vec<ArchiveEntry>, u64)> {
+    let mut count = [0u8; 4];
+    reader.read_exact(&mut count).map_err(|err| Error::from(err).truncated_at(ARCHIVE_HEADER_SIZE))?;
+
+    let mut entries = Vec::new();
+    let mut offset = ARCHIVE_HEADER_SIZE + count.len() as u64;
+    for _ in 0..u32::from_le_bytes(count) {
+        let entry = ArchiveEntry::read_from(reader, offset)?.ok_or_else(|| Error::corrupt(offset, "Unexpected end of entry table"))?;
+        offset += entry.record_size();
+        entries.push(entry);
+    }
+
+    Ok((entries, offset))
+}
+
+// Compresses a single file with archive settings and appends its record and compressed stream to the writer
+fn append_compressed<W: Write>(writer: &mut W, entry: &mut ArchiveEntry, source: &Path, settings: &ArchiveSettings) -> Result<()> {
+    // Empty files are stored without a compressed stream
+    if entry.size == 0 {
+        return Ok(entry.write_to(writer)?);
+    }
+
+    // Record goes first, so compressed data is kept until its size is known
+    let mut input = BufReader::new(File::open(source)?).take(entry.size);
+    let result = Container::encode_stream_with_size(&mut input, SpillBuffer::new(), settings.codec_id, &settings.pipeline,
+                                                    settings.checksum, Some(entry.size));
+    if input.limit() != 0 {
+        return Err(file_changed(source));
+    }
+    let compressed = result?;
+
+    entry.compressed_size = compressed.len();
+    entry.write_to(writer)?;
+    std::io::copy(&mut compressed.into_reader()?, writer)?;
+    Ok(())
+}
+
+fn write_solid_archive(archive_path: &str, entries: &[ArchiveEntry], contents: SpillBuffer, settings: &ArchiveSettings) -> Result<()> {
+    let mut compressed = SpillBuffer::new();
+    if !contents.is_empty() {
+        let contents_size = contents.len();
+        compressed = Container::encode_stream_with_size(&mut contents.into_reader()?, compressed, settings.codec_id, &settings.pipeline,
+                                                        settings.checksum, Some(contents_size))?;
+    }
+
+    let mut writer = BufWriter::new(File::create(archive_path)?);
+    writer.write_all(&ARCHIVE_MAGIC)?;
+    writer.write_all(&[ARCHIVE_VERSION, FLAG_SOLID])?;
+    writer.write_all(&(entries.len() as u32).to_le_bytes())?;
+    for entry in entries {
+        entry.write_to(&mut writer)?;
+    }
+
+    std::io::copy(&mut compressed.into_reader()?, &mut writer)?;
+    Ok(writer.flush()?)
+}
+
+// Contents of all files are concatenated into a single stream
+fn append_contents(contents: &mut SpillBuffer, sources: &[(PathBuf, ArchiveEntry)]) -> Result<()> {
+    for (source, entry) in sources.iter().filter(|(_, entry)| !entry.is_dir) {
+        copy_exact(&mut File::open(source)?, contents, entry.size).map_err(|_| file_changed(source))?;
+    }
+    Ok(())
+}
+
+pub fn create_archive(archive_path: &str, inputs: &[&str], settings: &ArchiveSettings) -> Result<()> {
+    let sources = collect_entries(inputs, archive_path)?;
+
+    if settings.solid {
+        let mut contents = SpillBuffer::new();
+        append_contents(&mut contents, &sources)?;
+
+        let entries: Vec<ArchiveEntry> = sources.into_iter().map(|(_, entry)| entry).collect();
+        return write_solid_archive(archive_path, &entries, contents, settings);
+    }
+
+    let mut writer = BufWriter::new(File::create(archive_path)?);
+    writer.write_all(&ARCHIVE_MAGIC)?;
+    writer.write_all(&[ARCHIVE_VERSION, 0])?;
+
+    for (source, mut entry) in sources {
+        if entry.is_dir {
+            entry.write_to(&mut writer)?;
+        } else {
+            append_compressed(&mut writer, &mut entry, &source, settings)?;
+        }
+    }
+
+    Ok(writer.flush()?)
+}
+
+pub fn list_archive(archive_path: &str) -> Result<Vec<ArchiveEntry>> {
+    let mut reader = BufReader::new(File::open(archive_path)?);
+    let flags = read_archive_header(&mut reader)?;
+
+    if flags & FLAG_SOLID != 0 {
+        return Ok(read_solid_table(&mut reader)?.0);
+    }
+
+    let mut entries = Vec::new();
+    let mut offset = ARCHIVE_HEADER_SIZE;
+    while let Some(entry) = ArchiveEntry::read_from(&mut reader, offset)? {
+        reader.seek_relative(entry.compressed_size as i64)?;
+        offset += entry.record_size() + entry.compressed_size;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+// Sizes are declared by the archive itself, so their total is checked against the caller's limit before decoding
+fn check_declared_size(entries: &[ArchiveEntry], max_output_size: u64) -> Result<u64> {
+    let contents_size = entries.iter().filter(|entry| !entry.is_dir).fold(0u64, |sum, entry| sum.saturating_add(entry.size));
+    if contents_size > max_output_size {
+        return Err(Error::LimitExceeded { size: contents_size, limit: max_output_size });
+    }
+    Ok(contents_size)
+}
+
+// Decoder of the solid stream (the rest of the archive after the entry table), which holds
+// contents of all files and nothing else
+fn open_solid_stream<'a, R: Read + 'a>(reader: R, entries: &[ArchiveEntry], stream_offset: u64, max_output_size: u64)
+    -> Result<(Box<dyn Read + 'a>, u64)> {
+    let contents_size = check_declared_size(entries, max_output_size)?;
+    if contents_size == 0 {
+        return Ok((Box::new(std::io::empty()), 0));
+    }
+
+    let decoder = Container::open_decoder_with_limit(reader, contents_size).map_err(|err| err.at_offset(stream_offset))?;
+    Ok((decoder, contents_size))
+}
+
+// Errors of a decoder are relative to the start of its stream
+fn stream_error(err: std::io::Error, stream_offset: u64) -> Error {
+    Error::from(err).at_offset(stream_offset).truncated_at(stream_offset)
+}
+
+// Reading past the last file makes the decoder check the stream end and its checksums
+fn finish_solid_stream(contents: &mut dyn Read, stream_offset: u64) -> Result<()> {
+    let mut extra = [0u8; 1];
+    if contents.read(&mut extra).map_err(|err| stream_error(err, stream_offset))? != 0 {
+        return Err(Error::corrupt(stream_offset, "Solid stream is longer than archived files"));
+    }
+    Ok(())
+}
+
+fn create_output_file(output_path: &Path) -> Result<BufWriter<File>> {
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(BufWriter::new(File::create(output_path)?))
+}
+
+fn restore_entry(output_path: &Path, entry: &ArchiveEntry) -> Result<()> {
+    set_mtime(output_path, entry.mtime)?;
+    Ok(set_file_mode(output_path, entry.mode)?)
+}
+
+fn extract_solid<R: Read>(reader: &mut R, output_dir: &Path, max_output_size: u64) -> Result<Vec<ArchiveEntry>> {
+    let (entries, stream_offset) = read_solid_table(reader)?;
+    let (mut contents, _) = open_solid_stream(reader, &entries, stream_offset, max_output_size)?;
+
+    for entry in entries.iter().filter(|entry| entry.is_dir) {
+        fs::create_dir_all(safe_output_path(output_dir, &entry.path)?)?;
+    }
+
+    for entry in entries.iter().filter(|entry| !entry.is_dir) {
+        let output_path = safe_output_path(output_dir, &entry.path)?;
+        let mut writer = create_output_file(&output_path)?;
+        copy_exact(&mut contents, &mut writer, entry.size).map_err(|err| stream_error(err, stream_offset))?;
+        writer.flush()?;
+        drop(writer);
+
+        restore_entry(&output_path, entry)?;
+    }
+
+    finish_solid_stream(&mut contents, stream_offset)?;
+    Ok(entries)
+}
+
+// Compressed data of a non-solid entry is a complete stream of its own
+fn decode_entry<R: Read, W: Write>(data: R, writer: &mut W, entry: &ArchiveEntry, max_output_size: u64) -> Result<()> {
+    let mut decoder = Container::open_decoder_with_limit(data, entry.size.min(max_output_size))?;
+    let decoded_size = std::io::copy(&mut decoder, writer)?;
+    if decoded_size != entry.size {
+        return Err(Error::corrupt(0, format!("Entry {} decoded to {} bytes instead of {}", entry.path, decoded_size, entry.size)));
+    }
+    Ok(())
+}
+
+// Every decoder is limited to what is left of max_output_size
+fn extract_entries<R: Read>(reader: &mut R, output_dir: &Path, max_output_size: u64) -> Result<Vec<ArchiveEntry>> {
+    let mut entries = Vec::new();
+    let mut offset = ARCHIVE_HEADER_SIZE;
+    let mut remaining = max_output_size;
+
+    while let Some(entry) = ArchiveEntry::read_from(reader, offset)? {
+        let data_offset = offset + entry.record_size();
+        let output_path = safe_output_path(output_dir, &entry.path)?;
+
+        if entry.is_dir {
+            fs::create_dir_all(&output_path)?;
+        } else {
+            if entry.size > remaining {
+                return Err(Error::LimitExceeded { size: max_output_size - remaining + entry.size, limit: max_output_size });
+            }
+
+            let mut writer = create_output_file(&output_path)?;
+            if entry.compressed_size != 0 {
+                let mut data = reader.take(entry.compressed_size);
+                decode_entry(&mut data, &mut writer, &entry, remaining).map_err(|err| err.at_offset(data_offset))?;
+                std::io::copy(&mut data, &mut std::io::sink())?;
+            } else if entry.size != 0 {
+                return Err(Error::corrupt(offset, format!("Entry {} has no compressed data", entry.path)));
+            }
+            writer.flush()?;
+            drop(writer);
+            remaining -= entry.size;
+
+            restore_entry(&output_path, &entry)?;
+        }
+
+        offset = data_offset + entry.compressed_size;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+// Extracts all entries into output directory, entries added later overwrite earlier ones with the same path.
+// Archives declaring more than max_output_size bytes of files are refused before anything is extracted.
+pub fn extract_archive(archive_path: &str, output_dir: &str, max_output_size: u64) -> Result<Vec<ArchiveEntry>> {
+    check_declared_size(&list_archive(archive_path)?, max_output_size)?;
+
+    let mut reader = BufReader::new(File::open(archive_path)?);
+    let flags = read_archive_header(&mut reader)?;
+    let output_dir = Path::new(output_dir);
+    fs::create_dir_all(output_dir)?;
+    let entries = if flags & FLAG_SOLID != 0 {
+        extract_solid(&mut reader, output_dir, max_output_size)?
+    } else {
+        extract_entries(&mut reader, output_dir, max_output_size)?
+    };
+
+    // Directory times are restored last, since creating files inside changes them
+    for entry in entries.iter().filter(|entry| entry.is_dir) {
+        restore_entry(&safe_output_path(output_dir, &entry.path)?, entry)?;
+    }
+
+    Ok(entries)
+}
+
+// Adds files to an existing archive. Non-solid archives are appended in place, solid ones are rebuilt.
+pub fn add_to_archive(archive_path: &str, inputs: &[&str], settings: &ArchiveSettings) -> Result<()> {
+    let mut reader = BufReader::new(File::open(archive_path)?);
+    let flags = read_archive_header(&mut reader)?;
+    let sources = collect_entries(inputs, archive_path)?;
+
+    if flags & FLAG_SOLID == 0 {
+        drop(reader);
+
+        let mut writer = BufWriter::new(OpenOptions::new().append(true).open(archive_path)?);
+        for (source, mut entry) in sources {
+            if entry.is_dir {
+                entry.write_to(&mut writer)?;
+            } else {
+                append_compressed(&mut writer, &mut entry, &source, settings)?;
+            }
+        }
+        return Ok(writer.flush()?);
+    }
+
+    // Decompress current solid stream and append new file contents to it
+    let (mut entries, stream_offset) = read_solid_table(&mut reader)?;
+    let mut contents = SpillBuffer::new();
+    {
+        let (mut current, contents_size) = open_solid_stream(&mut reader, &entries, stream_offset, Container::DEFAULT_MAX_OUTPUT_SIZE)?;
+        copy_exact(&mut current, &mut contents, contents_size).map_err(|err| stream_error(err, stream_offset))?;
+        finish_solid_stream(&mut current, stream_offset)?;
+    }
+    drop(reader);
+
+    append_contents(&mut contents, &sources)?;
+    entries.extend(sources.into_iter().map(|(_, entry)| entry));
+
+    write_solid_archive(archive_path, &entries, contents, settings)
+}
+
+#[cfg(all(test, any(feature = "huffman", feature = "lzw")))]
+mod tests {
+    use super::*;
+    use crate::Checksum::ChecksumKind;
+    use crate::Codec::registered_codecs;
+    use crate::Container::DEFAULT_MAX_OUTPUT_SIZE;
+    use crate::TransformationMethods::transform_pipeline;
+
+    // Directory under the system temp directory, removed with its contents when dropped
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("bsl-archive-test-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TestDir(path)
+        }
+
+        fn path(&self, relative: &str) -> String {
+            self.0.join(relative).to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn settings(solid: bool) -> ArchiveSettings {
+        ArchiveSettings {
+            codec_id: registered_codecs()[0].id(),
+            pipeline: transform_pipeline(1).unwrap(),
+            checksum: ChecksumSettings::new(ChecksumKind::CRC32, true),
+            solid,
+        }
+    }
+
+    fn write_file(path: &str, contents: &[u8], mode: u32, mtime: i64) {
+        fs::write(path, contents).unwrap();
+        set_mtime(Path::new(path), mtime).unwrap();
+        set_file_mode(Path::new(path), mode).unwrap();
+    }
+
+    // Tree: tree/, tree/a.txt, tree/sub/, tree/sub/b.bin, tree/sub/empty
+    fn create_tree(dir: &TestDir) -> Vec<(&'static str, Vec<u8>, u32, i64)> {
+        let files = vec![
+            ("tree/a.txt", b"mississippi river, mississippi state".to_vec(), 0o640, 1_000_000_000),
+            ("tree/sub/b.bin", (0..70_000u64).map(|i| (i * i % 251) as u8).collect(), 0o600, 1_200_000_000),
+            ("tree/sub/empty", Vec::new(), 0o444, 1_300_000_000),
+        ];
+
+        fs::create_dir_all(dir.path("tree/sub")).unwrap();
+        for (path, contents, mode, mtime) in files.iter() {
+            write_file(&dir.path(path), contents, *mode, *mtime);
+        }
+        set_mtime(Path::new(&dir.path("tree/sub")), 1_400_000_000).unwrap();
+        files
+    }
+
+    fn assert_restored(output_dir: &str, path: &str, contents: &[u8], mode: u32, mtime: i64) {
+        let restored_path = Path::new(output_dir).join(path);
+        let metadata = fs::metadata(&restored_path).unwrap();
+        assert_eq!(fs::read(&restored_path).unwrap(), contents, "{}", path);
+
+        let entry = entry_from_metadata(path.to_string(), &metadata);
+        assert_eq!(entry.mode, mode, "{}", path);
+        assert_eq!(entry.mtime, mtime, "{}", path);
+    }
+
+    #[test]
+    fn solid_and_non_solid_round_trip() {
+        for solid in [false, true] {
+            let dir = TestDir::new(if solid { "round-trip-solid" } else { "round-trip" });
+            let files = create_tree(&dir);
+
+            let archive_path = dir.path("tree.bsla");
+            create_archive(&archive_path, &[&dir.path("tree")], &settings(solid)).unwrap();
+
+            let entries = extract_archive(&archive_path, &dir.path("out"), DEFAULT_MAX_OUTPUT_SIZE).unwrap();
+            let paths: Vec<&str> = entries.iter().map(|entry| entry.path.as_str()).collect();
+            assert_eq!(paths, ["tree", "tree/a.txt", "tree/sub", "tree/sub/b.bin", "tree/sub/empty"]);
+            assert_eq!(list_archive(&archive_path).unwrap(), entries);
+
+            for (path, contents, mode, mtime) in files {
+                assert_restored(&dir.path("out"), path, &contents, mode, mtime);
+            }
+            assert_eq!(entry_from_metadata(String::new(), &fs::metadata(dir.path("out/tree/sub")).unwrap()).mtime, 1_400_000_000);
+        }
+    }
+
+    #[test]
+    fn add_keeps_existing_entries() {
+        for solid in [false, true] {
+            let dir = TestDir::new(if solid { "add-solid" } else { "add" });
+            let files = create_tree(&dir);
+            write_file(&dir.path("extra.txt"), b"added later", 0o644, 1_500_000_000);
+
+            let archive_path = dir.path("tree.bsla");
+            create_archive(&archive_path, &[&dir.path("tree/a.txt")], &settings(solid)).unwrap();
+            add_to_archive(&archive_path, &[&dir.path("extra.txt"), &dir.path("tree/sub")], &settings(solid)).unwrap();
+
+            let paths: Vec<String> = list_archive(&archive_path).unwrap().into_iter().map(|entry| entry.path).collect();
+            assert_eq!(paths, ["a.txt", "extra.txt", "sub", "sub/b.bin", "sub/empty"]);
+
+            extract_archive(&archive_path, &dir.path("out"), DEFAULT_MAX_OUTPUT_SIZE).unwrap();
+            assert_restored(&dir.path("out"), "extra.txt", b"added later", 0o644, 1_500_000_000);
+            for (path, contents, mode, mtime) in files {
+                assert_restored(&dir.path("out"), path.strip_prefix("tree/").unwrap(), &contents, mode, mtime);
+            }
+        }
+    }
+
+    #[test]
+    fn declared_sizes_over_limit_are_refused() {
+        let dir = TestDir::new("bomb");
+        let files = create_tree(&dir);
+        let total_size: u64 = files.iter().map(|(_, contents, _, _)| contents.len() as u64).sum();
+
+        for solid in [false, true] {
+            let archive_path = dir.path("tree.bsla");
+            create_archive(&archive_path, &[&dir.path("tree")], &settings(solid)).unwrap();
+
+            let result = extract_archive(&archive_path, &dir.path("out"), total_size - 1);
+            assert!(matches!(result, Err(Error::LimitExceeded { size, .. }) if size == total_size), "{:?}", result);
+            assert!(!Path::new(&dir.path("out")).exists());
+            extract_archive(&archive_path, &dir.path("out"), total_size).unwrap();
+            fs::remove_dir_all(dir.path("out")).unwrap();
+        }
+
+        // Small stream declaring a huge file, stored both ways
+        let settings = settings(false);
+        let stream = Container::compress(b"tiny", settings.codec_id, &settings.pipeline, settings.checksum).unwrap();
+        let entry = ArchiveEntry { path: "bomb".to_string(), is_dir: false, mode: 0o644, mtime: 0, size: 1 << 40, compressed_size: stream.len() as u64 };
+
+        let mut archive = ARCHIVE_MAGIC.to_vec();
+        archive.extend_from_slice(&[ARCHIVE_VERSION, 0]);
+        entry.write_to(&mut archive).unwrap();
+        archive.extend_from_slice(&stream);
+        fs::write(dir.path("bomb.bsla"), &archive).unwrap();
+
+        let mut solid_archive = ARCHIVE_MAGIC.to_vec();
+        solid_archive.extend_from_slice(&[ARCHIVE_VERSION, FLAG_SOLID]);
+        solid_archive.extend_from_slice(&1u32.to_le_bytes());
+        ArchiveEntry { compressed_size: 0, ..entry }.write_to(&mut solid_archive).unwrap();
+        solid_archive.extend_from_slice(&stream);
+        fs::write(dir.path("solid_bomb.bsla"), &solid_archive).unwrap();
+
+        for name in ["bomb.bsla", "solid_bomb.bsla"] {
+            let result = extract_archive(&dir.path(name), &dir.path("out"), DEFAULT_MAX_OUTPUT_SIZE);
+            assert!(matches!(result, Err(Error::LimitExceeded { size, .. }) if size == 1 << 40), "{}: {:?}", name, result);
+            assert!(!Path::new(&dir.path("out/bomb")).exists());
+        }
+    }
+
+    #[test]
+    fn safe_output_path_rejects_escaping_paths() {
+        let output_dir = Path::new("out");
+        assert_eq!(safe_output_path(output_dir, "a/b").unwrap(), Path::new("out/a/b"));
+
+        for path in ["../x", "/abs", "a/../../x", "./a", ""] {
+            assert!(matches!(safe_output_path(output_dir, path), Err(Error::InvalidParameter(_))), "{}", path);
+        }
+    }
+
+    #[test]
+    fn unsafe_entry_path_is_corrupt_at_its_record() {
+        let dir = TestDir::new("unsafe");
+        let entry = ArchiveEntry { path: "../x".to_string(), is_dir: false, mode: 0o644, mtime: 0, size: 0, compressed_size: 0 };
+
+        let mut archive = ARCHIVE_MAGIC.to_vec();
+        archive.extend_from_slice(&[ARCHIVE_VERSION, 0]);
+        entry.write_to(&mut archive).unwrap();
+        fs::write(dir.path("unsafe.bsla"), &archive).unwrap();
+
+        // Path follows its two length bytes
+        let result = extract_archive(&dir.path("unsafe.bsla"), &dir.path("out"), DEFAULT_MAX_OUTPUT_SIZE);
+        assert!(matches!(result, Err(Error::CorruptData { offset, .. }) if offset == ARCHIVE_HEADER_SIZE + 2));
+        assert!(!Path::new(&dir.path("x")).exists());
+
+        fs::write(dir.path("truncated.bsla"), &archive[..archive.len() - 5]).unwrap();
+        assert!(matches!(list_archive(&dir.path("truncated.bsla")), Err(Error::CorruptData { .. })));
+    }
+
+    #[test]
+    fn inputs_without_a_name_are_named_after_their_directory() {
+        let dir = TestDir::new("unnamed");
+        create_tree(&dir);
+
+        // Archive inside the archived directory is not archived itself, not even by add
+        let archive_path = dir.path("tree/tree.bsla");
+        create_archive(&archive_path, &[&dir.path("tree/sub/..")], &settings(false)).unwrap();
+        add_to_archive(&archive_path, &[&dir.path("tree/.")], &settings(false)).unwrap();
+
+        let paths: Vec<String> = list_archive(&archive_path).unwrap().into_iter().map(|entry| entry.path).collect();
+        let tree = ["tree", "tree/a.txt", "tree/sub", "tree/sub/b.bin", "tree/sub/empty"];
+        assert_eq!(paths, [tree, tree].concat());
+    }
+
+    #[test]
+    fn overlong_path_is_refused() {
+        let entry = ArchiveEntry { path: "a".repeat(u16::MAX as usize + 1), is_dir: true, mode: 0o755, mtime: 0, size: 0, compressed_size: 0 };
+        let result = entry.write_to(&mut Vec::new()).map_err(Error::from);
+        assert!(matches!(result, Err(Error::InvalidParameter(_))), "{:?}", result);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_inside_directories_are_skipped() {
+        let dir = TestDir::new("symlinks");
+        create_tree(&dir);
+        std::os::unix::fs::symlink("..", dir.path("tree/sub/up")).unwrap();
+        std::os::unix::fs::symlink("a.txt", dir.path("tree/link")).unwrap();
+
+        let archive_path = dir.path("tree.bsla");
+        create_archive(&archive_path, &[&dir.path("tree")], &settings(false)).unwrap();
+
+        let paths: Vec<String> = list_archive(&archive_path).unwrap().into_iter().map(|entry| entry.path).collect();
+        assert_eq!(paths, ["tree", "tree/a.txt", "tree/sub", "tree/sub/b.bin", "tree/sub/empty"]);
+
+        // Links named as inputs are followed
+        create_archive(&archive_path, &[&dir.path("tree/link")], &settings(false)).unwrap();
+        assert_eq!(list_archive(&archive_path).unwrap()[0].size, 36);
+    }
+}
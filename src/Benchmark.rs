@@ -0,0 +1,179 @@
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use Lab5::Checksum::ChecksumSettings;
+use Lab5::Codec::find_codec;
+use Lab5::Container;
+use Lab5::TransformationMethods::TransformStage;
+
+use crate::MemoryUsage;
+
+// Result of compressing and decompressing one input in memory
+#[derive(Clone, Debug)]
+pub struct Measurement {
+    pub file: String,
+    pub codec: &'static str,
+    pub pipeline: Vec<TransformStage>,
+    pub original_size: u64,
+    pub compressed_size: u64,
+    pub encode_time: Duration,
+    pub decode_time: Duration,
+    pub peak_memory: u64,   // Heap bytes on top of the input, the larger of encoding and decoding
+    pub verified: bool,     // Decoding succeeded and restored the input
+}
+
+impl Measurement {
+    // Compressed size in percent of the original
+    pub fn ratio(&self) -> f64 {
+        if self.original_size == 0 { 0.0 } else { self.compressed_size as f64 / self.original_size as f64 * 100.0 }
+    }
+
+    pub fn bits_per_byte(&self) -> f64 {
+        if self.original_size == 0 { 0.0 } else { self.compressed_size as f64 * 8.0 / self.original_size as f64 }
+    }
+
+    pub fn encode_throughput(&self) -> f64 {
+        throughput(self.original_size, self.encode_time)
+    }
+
+    pub fn decode_throughput(&self) -> f64 {
+        throughput(self.original_size, self.decode_time)
+    }
+}
+
+// MB/s of original data
+fn throughput(size: u64, time: Duration) -> f64 {
+    if time.is_zero() { 0.0 } else { size as f64 / (1 << 20) as f64 / time.as_secs_f64() }
+}
+
+// Failing to encode is an error, a failed round-trip is recorded in the measurement
+pub fn measure(file: &str, data: &[u8], codec_id: u8, pipeline: &[TransformStage], checksum: ChecksumSettings)
+    -> Result<Measurement, String> {
+    let baseline = MemoryUsage::reset_peak();
+    let start = Instant::now();
+    let compressed = Container::compress(data, codec_id, pipeline, checksum)
+                               .map_err(|err| format!("Compression failed: {}", err))?;
+    let encode_time = start.elapsed();
+    let encode_memory = MemoryUsage::peak() - baseline;
+
+    let baseline = MemoryUsage::reset_peak();
+    let start = Instant::now();
+    let mut decompressed = Vec::with_capacity(data.len());
+    let decoded = Container::open_decoder(compressed.as_slice()).and_then(|mut decoder| Ok(decoder.read_to_end(&mut decompressed)?));
+    let decode_time = start.elapsed();
+    let decode_memory = MemoryUsage::peak() - baseline;
+
+    if let Err(err) = &decoded {
+        eprintln!("{}: decompression failed: {}", file, err);
+    }
+
+    Ok(Measurement {
+        file: file.to_string(),
+        codec: find_codec(codec_id).map_or("unknown", |codec| codec.name()),
+        pipeline: pipeline.to_vec(),
+        original_size: data.len() as u64,
+        compressed_size: compressed.len() as u64,
+        encode_time,
+        decode_time,
+        peak_memory: encode_memory.max(decode_memory) as u64,
+        verified: decoded.is_ok() && decompressed == data,
+    })
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn write_csv(measurements: &[Measurement], writer: &mut dyn Write) -> std::io::Result<()> {
+    writeln!(writer, "file,codec,pipeline,original_size,compressed_size,ratio_percent,bits_per_byte,\
+                      encode_mb_per_s,decode_mb_per_s,peak_memory_bytes,verified")?;
+    for m in measurements {
+        writeln!(writer, "{},{},{},{},{},{:.3},{:.4},{:.3},{:.3},{},{}", csv_field(&m.file), m.codec, csv_field(&format!("{:?}", m.pipeline)),
+                 m.original_size, m.compressed_size, m.ratio(), m.bits_per_byte(),
+                 m.encode_throughput(), m.decode_throughput(), m.peak_memory, m.verified)?;
+    }
+    Ok(())
+}
+
+// One table per file, the best ratio of each file is in bold
+pub fn write_markdown(measurements: &[Measurement], writer: &mut dyn Write) -> std::io::Result<()> {
+    let mut files: Vec<&str> = Vec::new();
+    for m in measurements {
+        if !files.contains(&m.file.as_str()) {
+            files.push(&m.file);
+        }
+    }
+
+    for (file_id, &file) in files.iter().enumerate() {
+        let rows: Vec<&Measurement> = measurements.iter().filter(|m| m.file == file).collect();
+        let best_size = rows.iter().map(|m| m.compressed_size).min().unwrap_or(0);
+
+        if file_id > 0 {
+            writeln!(writer)?;
+        }
+        writeln!(writer, "## {} ({} bytes)\n", file.replace('|', "\\|"), rows[0].original_size)?;
+        writeln!(writer, "| Codec | Pipeline | Compressed | Ratio | Bits/byte | Encode MB/s | Decode MB/s | Peak memory | Verified |")?;
+        writeln!(writer, "|---|---|---:|---:|---:|---:|---:|---:|:---:|")?;
+        for m in rows {
+            let ratio = format!("{:.2}%", m.ratio());
+            let ratio = if m.compressed_size == best_size { format!("**{}**", ratio) } else { ratio };
+            writeln!(writer, "| {} | {:?} | {} | {} | {:.3} | {:.2} | {:.2} | {} | {} |", m.codec, m.pipeline, m.compressed_size, ratio,
+                     m.bits_per_byte(), m.encode_throughput(), m.decode_throughput(), format_memory(m.peak_memory),
+                     if m.verified { "yes" } else { "**no**" })?;
+        }
+    }
+    Ok(())
+}
+
+fn format_memory(bytes: u64) -> String {
+    match bytes {
+        0..0x400 => format!("{} B", bytes),
+        0x400..0x100000 => format!("{:.1} KB", bytes as f64 / (1u64 << 10) as f64),
+        _ => format!("{:.1} MB", bytes as f64 / (1u64 << 20) as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<Measurement> {
+        let measurement = Measurement {
+            file: "data, v2.bin".to_string(),
+            codec: "lzw",
+            pipeline: vec![TransformStage::BWT, TransformStage::MTF],
+            original_size: 1000,
+            compressed_size: 250,
+            encode_time: Duration::from_millis(1),
+            decode_time: Duration::from_millis(2),
+            peak_memory: 3 << 20,
+            verified: true,
+        };
+        vec![measurement.clone(), Measurement { codec: "huffman", compressed_size: 500, verified: false, ..measurement }]
+    }
+
+    #[test]
+    fn csv_has_a_row_per_measurement_with_quoted_fields() {
+        let mut csv = Vec::new();
+        write_csv(&sample(), &mut csv).unwrap();
+        let lines: Vec<String> = String::from_utf8(csv).unwrap().lines().map(String::from).collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], "\"data, v2.bin\",lzw,\"[BWT, MTF]\",1000,250,25.000,2.0000,0.954,0.477,3145728,true");
+    }
+
+    #[test]
+    fn markdown_marks_best_ratio_and_failed_round_trips() {
+        let mut markdown = Vec::new();
+        write_markdown(&sample(), &mut markdown).unwrap();
+        let markdown = String::from_utf8(markdown).unwrap();
+
+        assert!(markdown.starts_with("## data, v2.bin (1000 bytes)\n"));
+        assert!(markdown.contains("| lzw | [BWT, MTF] | 250 | **25.00%** | 2.000 | 0.95 | 0.48 | 3.0 MB | yes |"));
+        assert!(markdown.contains("| huffman | [BWT, MTF] | 500 | 50.00% | 4.000 | 0.95 | 0.48 | 3.0 MB | **no** |"));
+    }
+}
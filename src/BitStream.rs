@@ -1,13 +1,26 @@
 use std::cmp::min;
-use std::io::{Read, Seek, Write};
-use std::path::Path;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::fs::{File, OpenOptions};
 
 // Max size of buffer in buffered read (4KB)
 const BUFF_MAX_BYTE_SIZE: usize = 4096;
 
-fn create_error(message: &str) -> Result<(), std::io::Error> {
-    Err(std::io::Error::new(std::io::ErrorKind::Other, message))
+// Max number of bits in integer-based reads and writes
+pub const MAX_BITS_PER_CALL: usize = 64;
+
+fn too_many_bits(bit_len: usize) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                        format!("Cannot process {} bits at once, limit is {}", bit_len, MAX_BITS_PER_CALL))
+}
+
+fn low_bits_mask(bit_len: usize) -> u64 {
+    if bit_len >= 64 { u64::MAX } else { (1u64 << bit_len) - 1 }
+}
+
+// Integers are stored with the first stream bit in the lowest bit (LSB-first) or in the highest
+// of bit_len bits (MSB-first). Reversal converts between the two layouts.
+fn reverse_low_bits(value: u64, bit_len: usize) -> u64 {
+    if bit_len == 0 { 0 } else { value.reverse_bits() >> (64 - bit_len) }
 }
 
 pub fn bin_string_LSBF(bytes: &[u8]) -> String {
@@ -16,69 +29,352 @@ pub fn bin_string_LSBF(bytes: &[u8]) -> String {
         .map(|byte| format!("{:08b}", byte.reverse_bits()))
         .collect();
     
-    return result.join(" ");
+    result.join(" ")
+}
+
+pub fn bin_string_MSBF(bytes: &[u8]) -> String {
+    let result: Vec<String> = bytes
+        .iter()
+        .map(|byte| format!("{:08b}", byte))
+        .collect();
+    
+    result.join(" ")
+}
+
+// Order in which bits fill a byte. Bit sequences passed to and returned from streams are packed
+// in the same order as the stream itself, so whole bytes look identical in both orders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitOrder {
+    LSBFirst,   // Deflate, LZW in GIF
+    MSBFirst,   // JPEG entropy segments, bzip2, MPEG headers
+}
+
+// MSB-first streams are handled as LSB-first ones over bytes with reversed bits
+fn reverse_bits_in_place(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        *byte = byte.reverse_bits();
+    }
 }
 
-pub struct BitStream {
+// Reads bit sequences from any byte source
+pub struct BitReader<R: Read> {
     buff: Vec<u8>,
     bit_pointer: usize,
-    read_dir: bool,
-    file: File,
-    byte_chunk_size: usize
+    inner: R,
+    byte_chunk_size: usize,
+    buffer_start_bit: u64,      // Stream position of the first bit in the buffer
+    bit_order: BitOrder
 }
 
-impl BitStream {
-    pub fn new(file_path: &str, read_dir: bool) -> Self {
-        let buff: Vec<u8>;
-        
-        let file_stream: File;
-        if read_dir {
-            file_stream = File::open(file_path).unwrap();
-            buff = vec![0u8; BUFF_MAX_BYTE_SIZE];
+// Collects bit sequences and passes complete bytes to any byte sink
+pub struct BitWriter<W: Write> {
+    buff: Vec<u8>,
+    bit_pointer: usize,
+    inner: W,
+    bytes_written: u64,         // Bytes already passed to the sink
+    bit_order: BitOrder
+}
+
+impl BitReader<File> {
+    pub fn open(file_path: &str) -> Result<Self, std::io::Error> {
+        Ok(BitReader::new(File::open(file_path)?))
+    }
+}
+
+impl<R: Read> BitReader<R> {
+    pub fn new(inner: R) -> Self {
+        BitReader::with_bit_order(inner, BitOrder::LSBFirst)
+    }
+
+    pub fn with_bit_order(inner: R, bit_order: BitOrder) -> Self {
+        BitReader {
+            buff: vec![0u8; BUFF_MAX_BYTE_SIZE],
+            bit_pointer: 0,
+            inner,
+            byte_chunk_size: 0,
+            buffer_start_bit: 0,
+            bit_order
         }
-        else {
-            file_stream = OpenOptions::new().read(true)
-                                            .create(true)
-                                            .write(true)
-                                            .truncate(false)
-                                            .open(file_path).unwrap();
-            buff = Vec::new();
+    }
+
+    pub fn bit_order(&self) -> BitOrder {
+        self.bit_order
+    }
+
+    pub fn read_bit_sequence(&mut self, size: usize) -> Result<Vec<u8>, std::io::Error> {
+        let mut result: Vec<u8> = Vec::new();
+        let mut bits_read: usize = 0;
+
+        while bits_read != size {
+            // If chunk is empty -> read next
+            if (self.byte_chunk_size * 8 - self.bit_pointer) == 0 {
+                self.buffer_start_bit += (self.byte_chunk_size * 8) as u64;
+                self.byte_chunk_size = 0;
+                self.bit_pointer = 0;
+
+                let bytes_read = self.inner.read(&mut self.buff)?;
+                if bytes_read == 0 {
+                    // println!("Warning! Reached EOF for stream in read operation!");
+                    break;
+                }
+                self.byte_chunk_size = bytes_read;
+                self.bit_pointer = 0;
+
+                if self.bit_order == BitOrder::MSBFirst {
+                    reverse_bits_in_place(&mut self.buff[..bytes_read]);
+                }
+            }
+
+            let bits_to_move = min(self.byte_chunk_size * 8 - self.bit_pointer, size - bits_read);
+
+            // Move bits in portions of up to one byte, appending them right after already read bits
+            let mut bits_left = bits_to_move;
+            while bits_left != 0 {
+                let portion = min(bits_left, 8);
+                let byte_id = self.bit_pointer / 8;
+                let shift = self.bit_pointer % 8;
+
+                let mut value = (self.buff[byte_id] >> shift) as u16;
+                if shift + portion > 8 {
+                    value |= (self.buff[byte_id + 1] as u16) << (8 - shift);
+                }
+                let value = (value & ((1u16 << portion) - 1)) as u8;
+
+                let result_shift = bits_read % 8;
+                if result_shift == 0 {
+                    result.push(value);
+                } else {
+                    let last_id = result.len() - 1;
+                    result[last_id] |= value << result_shift;
+                    if result_shift + portion > 8 {
+                        result.push(value >> (8 - result_shift));
+                    }
+                }
+
+                bits_read += portion;
+                bits_left -= portion;
+                self.bit_pointer += portion;
+            }
         }
 
-        BitStream {
-            buff: buff,
-            bit_pointer: 0,
-            read_dir: read_dir,
-            file: file_stream,
-            byte_chunk_size: 0
+        if self.bit_order == BitOrder::MSBFirst {
+            reverse_bits_in_place(&mut result);
         }
+
+        Ok(result)
     }
 
-    pub fn clear_output_file(&self) -> Result<(), std::io::Error> {
-        if !self.read_dir {
-            // Truncate file data
-            self.file.set_len(0)?;
-            Ok(())
+    // Makes at least bit_len bits available in the buffer unless the stream ends earlier,
+    // returns number of available bits
+    fn fill_buffer(&mut self, bit_len: usize) -> Result<usize, std::io::Error> {
+        let available = self.byte_chunk_size * 8 - self.bit_pointer;
+        if available >= bit_len {
+            return Ok(available);
         }
-        else {
-            create_error("Cannot clear file in read mode")
+
+        // Move unread bytes to the start of the buffer
+        let start_id = self.bit_pointer / 8;
+        self.buff.copy_within(start_id..self.byte_chunk_size, 0);
+        self.byte_chunk_size -= start_id;
+        self.bit_pointer -= start_id * 8;
+        self.buffer_start_bit += (start_id * 8) as u64;
+
+        while self.byte_chunk_size * 8 - self.bit_pointer < bit_len {
+            let bytes_read = self.inner.read(&mut self.buff[self.byte_chunk_size..])?;
+            if bytes_read == 0 {
+                break;  // EOF
+            }
+
+            if self.bit_order == BitOrder::MSBFirst {
+                reverse_bits_in_place(&mut self.buff[self.byte_chunk_size..self.byte_chunk_size + bytes_read]);
+            }
+            self.byte_chunk_size += bytes_read;
+        }
+
+        Ok(self.byte_chunk_size * 8 - self.bit_pointer)
+    }
+
+    // Returns next bit_len bits without consuming them, bits past the end of the stream are zeros
+    pub fn peek_bits(&mut self, bit_len: usize) -> Result<u64, std::io::Error> {
+        if bit_len > MAX_BITS_PER_CALL {
+            return Err(too_many_bits(bit_len));
         }
+
+        let bits_to_take = min(self.fill_buffer(bit_len)?, bit_len);
+
+        let mut value = 0u64;
+        let mut bits_taken = 0;
+        let mut position = self.bit_pointer;
+        while bits_taken < bits_to_take {
+            let shift = position % 8;
+            let portion = min(8 - shift, bits_to_take - bits_taken);
+
+            let bits = (self.buff[position / 8] >> shift) as u64 & low_bits_mask(portion);
+            value |= bits << bits_taken;
+
+            bits_taken += portion;
+            position += portion;
+        }
+
+        match self.bit_order {
+            BitOrder::LSBFirst => Ok(value),
+            BitOrder::MSBFirst => Ok(reverse_low_bits(value, bit_len)),
+        }
+    }
+
+    pub fn consume(&mut self, bit_len: usize) -> Result<(), std::io::Error> {
+        let mut bits_left = bit_len;
+        while bits_left != 0 {
+            let available = self.fill_buffer(min(bits_left, BUFF_MAX_BYTE_SIZE * 8 / 2))?;
+            if available == 0 {
+                return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Not enough bits left in the stream"));
+            }
+
+            let portion = min(available, bits_left);
+            self.bit_pointer += portion;
+            bits_left -= portion;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_bits(&mut self, bit_len: usize) -> Result<u64, std::io::Error> {
+        if bit_len > MAX_BITS_PER_CALL {
+            return Err(too_many_bits(bit_len));
+        }
+
+        if self.fill_buffer(bit_len)? < bit_len {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Not enough bits left in the stream"));
+        }
+
+        let value = self.peek_bits(bit_len)?;
+        self.bit_pointer += bit_len;
+        Ok(value)
+    }
+
+    // Skips the rest of the current byte
+    pub fn align_to_byte(&mut self) -> Result<(), std::io::Error> {
+        self.consume((8 - self.bit_position() % 8) as usize % 8)
+    }
+
+    // Number of bits consumed from the start of the stream
+    pub fn bit_position(&self) -> u64 {
+        self.buffer_start_bit + self.bit_pointer as u64
+    }
+
+    // Bits already buffered but not read yet are lost
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read + Seek> BitReader<R> {
+    pub fn rewind_read_stream(&mut self) -> Result<(), std::io::Error> {
+        self.inner.rewind()?;
+        self.buff.clear();
+        self.buff.resize(BUFF_MAX_BYTE_SIZE, 0u8);
+        
+        self.bit_pointer = 0;
+        self.byte_chunk_size = 0;
+        self.buffer_start_bit = 0;
+
+        Ok(())
+    }
+
+    // Moves to the given bit position (counted like bit_position), data already in the buffer is reused
+    pub fn seek_bits(&mut self, position: u64) -> Result<(), std::io::Error> {
+        let buffer_end_bit = self.buffer_start_bit + (self.byte_chunk_size * 8) as u64;
+        if position >= self.buffer_start_bit && position <= buffer_end_bit {
+            self.bit_pointer = (position - self.buffer_start_bit) as usize;
+            return Ok(());
+        }
+
+        // Source is positioned right after the buffered bytes
+        let target_byte = position / 8;
+        self.inner.seek(SeekFrom::Current(target_byte as i64 - (buffer_end_bit / 8) as i64))?;
+
+        self.byte_chunk_size = 0;
+        self.bit_pointer = 0;
+        self.buffer_start_bit = target_byte * 8;
+
+        self.consume((position % 8) as usize)
+    }
+}
+
+impl BitWriter<File> {
+    // File is also opened for reading, so bits already written can be patched
+    pub fn create(file_path: &str) -> Result<Self, std::io::Error> {
+        let file = OpenOptions::new().read(true)
+                                     .write(true)
+                                     .create(true)
+                                     .truncate(true)
+                                     .open(file_path)?;
+        Ok(BitWriter::new(file))
+    }
+}
+
+impl<W: Write> BitWriter<W> {
+    pub fn new(inner: W) -> Self {
+        BitWriter::with_bit_order(inner, BitOrder::LSBFirst)
+    }
+
+    pub fn with_bit_order(inner: W, bit_order: BitOrder) -> Self {
+        BitWriter {
+            buff: Vec::new(),
+            bit_pointer: 0,
+            inner,
+            bytes_written: 0,
+            bit_order
+        }
+    }
+
+    pub fn bit_order(&self) -> BitOrder {
+        self.bit_order
     }
 
     pub fn write_bit_sequence(&mut self, in_buff: &[u8], bit_len: usize) -> Result<(), std::io::Error> {
-        if self.read_dir {
-            return create_error("This BitStream is in read mode");
+        if self.bit_order == BitOrder::MSBFirst {
+            let mut reversed = in_buff[..bit_len.div_ceil(8)].to_vec();
+            reverse_bits_in_place(&mut reversed);
+            return self.write_bits_LSBF(&reversed, bit_len);
         }
 
+        self.write_bits_LSBF(in_buff, bit_len)
+    }
+
+    // Writes low bit_len bits of value, see reverse_low_bits for the layout in each bit order
+    pub fn write_bits(&mut self, value: u64, bit_len: usize) -> Result<(), std::io::Error> {
+        if bit_len > MAX_BITS_PER_CALL {
+            return Err(too_many_bits(bit_len));
+        }
+
+        let value = value & low_bits_mask(bit_len);
+        let value = match self.bit_order {
+            BitOrder::LSBFirst => value,
+            BitOrder::MSBFirst => reverse_low_bits(value, bit_len),
+        };
+
+        self.write_bits_LSBF(&value.to_le_bytes(), bit_len)
+    }
+
+    // Pads the current byte with zero bits without passing it to the sink
+    pub fn align_to_byte(&mut self) {
+        self.bit_pointer = self.bit_pointer.div_ceil(8) * 8;
+    }
+
+    // Number of bits written from the start of the stream, including padding of flushed bytes
+    pub fn bit_position(&self) -> u64 {
+        self.bytes_written * 8 + self.bit_pointer as u64
+    }
+
+    fn write_bits_LSBF(&mut self, in_buff: &[u8], bit_len: usize) -> Result<(), std::io::Error> {
         let basic_shift = self.bit_pointer % 8;
         let full_bytes_to_write = bit_len / 8;
         let remaining_bits = bit_len % 8;
         
         if basic_shift == 0 {
             // Move full bytes to the stream buffer
-            for i in 0..full_bytes_to_write {
-                self.buff.push(in_buff[i]);
-            }
+            self.buff.extend_from_slice(&in_buff[..full_bytes_to_write]);
             
             // Handle remaining bits
             if remaining_bits != 0 {
@@ -89,18 +385,18 @@ impl BitStream {
             let mut last_byte_id = self.buff.len() - 1;
 
             // Move full bytes to the stream buffer
-            for i in 0..full_bytes_to_write {
+            for &in_byte in &in_buff[..full_bytes_to_write] {
                 // Append low bits to the last byte
-                self.buff[last_byte_id] |= in_buff[i] << basic_shift;
+                self.buff[last_byte_id] |= in_byte << basic_shift;
 
                 // Push high bits as a new byte
-                self.buff.push(in_buff[i] >> (8 - basic_shift));
+                self.buff.push(in_byte >> (8 - basic_shift));
                 last_byte_id += 1;
             }
 
             // Handle remaining bits
             if remaining_bits != 0 {
-                let last_input_byte_id = (bit_len + 7) / 8 - 1;
+                let last_input_byte_id = bit_len.div_ceil(8) - 1;
                 if remaining_bits + basic_shift > 8 {
                     self.buff[last_byte_id] |= in_buff[last_input_byte_id] << basic_shift;
                     self.buff.push((in_buff[last_input_byte_id] << (8 - remaining_bits)) >> (16 - remaining_bits - basic_shift));
@@ -113,112 +409,765 @@ impl BitStream {
         self.bit_pointer += bit_len;
 
         // println!("Buffer after write (LSB-F): {}", bin_string_LSBF(&self.buff));
+        if self.buff.len() > BUFF_MAX_BYTE_SIZE {
+            self.drain_full_bytes()?;
+        }
+
         Ok(())
     }
 
-    pub fn flush(&mut self) -> Result<(), std::io::Error> {
-        if self.read_dir {
-            return create_error("BitStream cannot be flushed in read mode");
+    // Buffer is kept in LSB-first order, bytes are converted to the stream order on output
+    fn write_out(&mut self, mut bytes: Vec<u8>) -> Result<(), std::io::Error> {
+        if self.bit_order == BitOrder::MSBFirst {
+            reverse_bits_in_place(&mut bytes);
         }
 
+        self.bytes_written += bytes.len() as u64;
+        self.inner.write_all(&bytes)
+    }
+
+    // Passes all complete bytes to the sink, a partially filled last byte stays in the buffer
+    fn drain_full_bytes(&mut self) -> Result<(), std::io::Error> {
+        let full_bytes = self.bit_pointer / 8;
+        let bytes: Vec<u8> = self.buff.drain(..full_bytes).collect();
+        self.write_out(bytes)?;
+
+        self.bit_pointer -= full_bytes * 8;
+
+        Ok(())
+    }
+
+    // Pads the last byte with zero bits, so the next write starts at a byte boundary
+    pub fn flush(&mut self) -> Result<(), std::io::Error> {
         // println!("Buffer on flush (LSB-F): {}", bin_string_LSBF(&self.buff));
 
-        self.file.write_all(&self.buff)?;
-        self.file.flush()?;
+        let bytes = std::mem::take(&mut self.buff);
+        self.write_out(bytes)?;
+        self.inner.flush()?;
 
-        self.buff.clear();
         self.bit_pointer = 0;
 
         Ok(())
     }
 
-    pub fn read_bit_sequence(&mut self, size: usize) -> Result<Vec<u8>, std::io::Error> {
-        if !self.read_dir {
-            return Err(create_error("This BitStream is in write mode").err().unwrap());
+    pub fn into_inner(mut self) -> Result<W, std::io::Error> {
+        BitWriter::flush(&mut self)?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Read + Write + Seek> BitWriter<W> {
+    // Overwrites bit_len bits at an earlier position (e.g. a length field written as a placeholder)
+    // with value in the same layout as write_bits. Current write position does not change.
+    pub fn patch_bits(&mut self, position: u64, value: u64, bit_len: usize) -> Result<(), std::io::Error> {
+        if bit_len > MAX_BITS_PER_CALL {
+            return Err(too_many_bits(bit_len));
+        }
+        if position + bit_len as u64 > self.bit_position() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Cannot patch bits that are not written yet"));
+        }
+        if bit_len == 0 {
+            return Ok(());
         }
 
-        let mut result: Vec<u8> = Vec::new();
-        let mut bits_read: usize = 0;
+        let stream_bit = |i: usize| -> u8 {
+            match self.bit_order {
+                BitOrder::LSBFirst => ((value >> i) & 1) as u8,
+                BitOrder::MSBFirst => ((value >> (bit_len - 1 - i)) & 1) as u8,
+            }
+        };
 
-        while bits_read != size {
-            // If chunk is empty -> read next
-            if (self.byte_chunk_size * 8 - self.bit_pointer) == 0 {
-                let bytes_read = self.file.read(&mut self.buff)?;
-                if bytes_read == 0 {
-                    // println!("Warning! Reached EOF for stream in read operation!");
-                    return Ok(result);
+        // Bytes already passed to the sink are read, modified and written back
+        let first_byte = position / 8;
+        let last_byte = (position + bit_len as u64 - 1) / 8;
+        if first_byte < self.bytes_written {
+            let sink_last_byte = min(last_byte, self.bytes_written - 1);
+            let mut bytes = vec![0u8; (sink_last_byte - first_byte + 1) as usize];
+
+            // Sink is positioned right after the written bytes
+            self.inner.flush()?;
+            self.inner.seek(SeekFrom::Current(first_byte as i64 - self.bytes_written as i64))?;
+            self.inner.read_exact(&mut bytes)?;
+
+            for i in 0..bit_len {
+                let bit_id = position + i as u64;
+                if bit_id / 8 > sink_last_byte {
+                    break;
                 }
-                self.byte_chunk_size = bytes_read;
-                self.bit_pointer = 0;
+
+                let byte = &mut bytes[(bit_id / 8 - first_byte) as usize];
+                let shift = match self.bit_order {
+                    BitOrder::LSBFirst => bit_id % 8,
+                    BitOrder::MSBFirst => 7 - bit_id % 8,
+                };
+                *byte = (*byte & !(1 << shift)) | (stream_bit(i) << shift);
             }
 
-            let bit_chunk_size = self.byte_chunk_size * 8;
-            let basic_shift = self.bit_pointer % 8;
-            
-            let bits_to_move = min(bit_chunk_size - self.bit_pointer, size - bits_read);
-            let start_id = self.bit_pointer / 8;
-            let end_id = (self.bit_pointer + bits_to_move + 7) / 8;
-
-            // Copy all the bytes from start to end
-            let curr_start_id = result.len();
-            result.append(&mut self.buff[start_id..end_id].to_vec());
-
-            // Set bytes in correct position
-            if basic_shift != 0 {
-                for i in curr_start_id..result.len() - 1 {
-                    result[i] = (result[i] >> basic_shift) | (result[i + 1] << (8 - basic_shift));
-                }
+            self.inner.seek(SeekFrom::Current(-(bytes.len() as i64)))?;
+            self.inner.write_all(&bytes)?;
+            self.inner.seek(SeekFrom::Current(self.bytes_written as i64 - sink_last_byte as i64 - 1))?;
+        }
+
+        // The rest is still in the buffer (kept in LSB-first order)
+        for i in 0..bit_len {
+            let bit_id = position + i as u64;
+            if bit_id / 8 < self.bytes_written {
+                continue;
+            }
+
+            let byte = &mut self.buff[(bit_id / 8 - self.bytes_written) as usize];
+            *byte = (*byte & !(1 << (bit_id % 8))) | (stream_bit(i) << (bit_id % 8));
+        }
+
+        Ok(())
+    }
+}
+
+// Universal integer codes. Codes are bit strings written first bit first, so they look the same
+// in both bit orders: MSB-first streams keep them as is, LSB-first streams start from the lowest bit.
+
+fn invalid_input(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+fn invalid_code(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+// Number of significant bits, 0 for 0
+fn bit_length(value: u64) -> usize {
+    64 - value.leading_zeros() as usize
+}
+
+impl<W: Write> BitWriter<W> {
+    // Writes low bit_len bits of value starting from the most significant one
+    fn write_bit_string(&mut self, value: u64, bit_len: usize) -> Result<(), std::io::Error> {
+        match self.bit_order {
+            BitOrder::LSBFirst => self.write_bits(reverse_low_bits(value, bit_len), bit_len),
+            BitOrder::MSBFirst => self.write_bits(value, bit_len),
+        }
+    }
+
+    fn write_repeated_bit(&mut self, bit: u64, mut count: u64) -> Result<(), std::io::Error> {
+        let pattern = if bit == 0 { 0 } else { u64::MAX };
+        while count != 0 {
+            let portion = min(count, MAX_BITS_PER_CALL as u64) as usize;
+            self.write_bits(pattern, portion)?;
+            count -= portion as u64;
+        }
+        Ok(())
+    }
+
+    // N zeros followed by N + 1 bits of value (value >= 1)
+    pub fn write_elias_gamma(&mut self, value: u64) -> Result<(), std::io::Error> {
+        if value == 0 {
+            return Err(invalid_input("Elias gamma code is defined for values >= 1"));
+        }
 
-                let last_id = result.len() - 1;
-                result[last_id] >>= basic_shift;
+        let value_len = bit_length(value);
+        self.write_bits(0, value_len - 1)?;
+        self.write_bit_string(value, value_len)
+    }
+
+    // Gamma coded length followed by value without its leading 1 (value >= 1)
+    pub fn write_elias_delta(&mut self, value: u64) -> Result<(), std::io::Error> {
+        if value == 0 {
+            return Err(invalid_input("Elias delta code is defined for values >= 1"));
+        }
+
+        let value_len = bit_length(value);
+        self.write_elias_gamma(value_len as u64)?;
+        self.write_bit_string(value, value_len - 1)
+    }
+
+    // Zeckendorf representation from the smallest Fibonacci number, terminated by an extra 1 (value >= 1)
+    pub fn write_fibonacci(&mut self, value: u64) -> Result<(), std::io::Error> {
+        if value == 0 {
+            return Err(invalid_input("Fibonacci code is defined for values >= 1"));
+        }
+
+        // Fibonacci numbers 1, 2, 3, 5, ... not greater than value
+        let mut fibs: Vec<u64> = vec![1, 2];
+        while let Some(next) = fibs[fibs.len() - 1].checked_add(fibs[fibs.len() - 2]) {
+            if next > value {
+                break;
             }
+            fibs.push(next);
+        }
+        while *fibs.last().unwrap() > value {
+            fibs.pop();
+        }
 
-            // Remove last byte if it is not used
-            if (bits_to_move + 7) / 8 < (result.len() - curr_start_id) {
-                result.pop();
+        let mut bits = vec![0u64; fibs.len()];
+        let mut remainder = value;
+        for (i, &fib) in fibs.iter().enumerate().rev() {
+            if fib <= remainder {
+                bits[i] = 1;
+                remainder -= fib;
             }
+        }
 
-            // Clear unused high bits in the last byte
-            let rem_bits = (bits_to_move % 8) as u8;
-            if rem_bits != 0 {
-                let last_id = result.len() - 1;
-                result[last_id] = (result[last_id] << (8 - rem_bits)) >> (8 - rem_bits);
+        for bit in bits {
+            self.write_bits(bit, 1)?;
+        }
+        self.write_bits(1, 1)
+    }
+
+    // Quotient value >> k in unary (ones terminated by a zero), then k low bits
+    pub fn write_golomb_rice(&mut self, value: u64, k: usize) -> Result<(), std::io::Error> {
+        if k >= 64 {
+            return Err(invalid_input("Golomb-Rice parameter must be less than 64"));
+        }
+
+        self.write_repeated_bit(1, value >> k)?;
+        self.write_bits(0, 1)?;
+        self.write_bit_string(value, k)
+    }
+
+    // Order-k Exp-Golomb: value + 2^k written as in Elias gamma, without its k trailing bits in the prefix
+    pub fn write_exp_golomb(&mut self, value: u64, k: usize) -> Result<(), std::io::Error> {
+        if k >= 64 {
+            return Err(invalid_input("Exp-Golomb order must be less than 64"));
+        }
+
+        let shifted = value.checked_add(1 << k).ok_or_else(|| invalid_input("Value is too large for Exp-Golomb code"))?;
+        let value_len = bit_length(shifted);
+        self.write_bits(0, value_len - 1 - k)?;
+        self.write_bit_string(shifted, value_len)
+    }
+
+    // Unsigned LEB128: 7 bits per byte starting from the lowest ones, high bit marks continuation
+    pub fn write_leb128(&mut self, mut value: u64) -> Result<(), std::io::Error> {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+
+            if value == 0 {
+                return self.write_bits(byte as u64, 8);
             }
+            self.write_bits((byte | 0x80) as u64, 8)?;
+        }
+    }
+}
 
-            // If we have previous chunk in result, need to merge bytes
-            if curr_start_id != 0 && basic_shift != 0 {
-                let shift_in_chunks = (bits_read % 8) as u8;
-                if shift_in_chunks != 0 {
-                    for i in curr_start_id..result.len() {
-                        result[i - 1] |= result[i] << (8 - shift_in_chunks);
-                        result[i] = result[i] >> shift_in_chunks;
-                    }
+impl<R: Read> BitReader<R> {
+    fn read_bit_string(&mut self, bit_len: usize) -> Result<u64, std::io::Error> {
+        let value = self.read_bits(bit_len)?;
+        match self.bit_order {
+            BitOrder::LSBFirst => Ok(reverse_low_bits(value, bit_len)),
+            BitOrder::MSBFirst => Ok(value),
+        }
+    }
 
-                    if rem_bits <= (8 - shift_in_chunks) {
-                        result.pop();
-                    }
+    // Counts bits equal to bit until the opposite one, which is consumed too
+    fn read_run(&mut self, bit: u64, max_run: u64) -> Result<u64, std::io::Error> {
+        let mut run = 0;
+        while self.read_bits(1)? == bit {
+            run += 1;
+            if run > max_run {
+                return Err(invalid_code("Integer code is too long"));
+            }
+        }
+        Ok(run)
+    }
+
+    pub fn read_elias_gamma(&mut self) -> Result<u64, std::io::Error> {
+        let zeros = self.read_run(0, 63)? as usize;
+        Ok(1 << zeros | self.read_bit_string(zeros)?)
+    }
+
+    pub fn read_elias_delta(&mut self) -> Result<u64, std::io::Error> {
+        let value_len = self.read_elias_gamma()?;
+        if value_len > 64 {
+            return Err(invalid_code("Elias delta code is too long"));
+        }
+
+        let value_len = value_len as usize;
+        Ok(1 << (value_len - 1) | self.read_bit_string(value_len - 1)?)
+    }
+
+    pub fn read_fibonacci(&mut self) -> Result<u64, std::io::Error> {
+        let (mut fib, mut next_fib) = (1u64, 2u64);
+        let mut value = 0u64;
+        let mut previous_bit = 0;
+
+        loop {
+            let bit = self.read_bits(1)?;
+            if bit == 1 && previous_bit == 1 {
+                return Ok(value);
+            }
+
+            if bit == 1 {
+                value = value.checked_add(fib).ok_or_else(|| invalid_code("Fibonacci code is too long"))?;
+            }
+            previous_bit = bit;
+
+            // Fibonacci numbers above u64 can only be followed by the terminating 1
+            let following = fib.saturating_add(next_fib);
+            fib = next_fib;
+            next_fib = following;
+        }
+    }
+
+    pub fn read_golomb_rice(&mut self, k: usize) -> Result<u64, std::io::Error> {
+        if k >= 64 {
+            return Err(invalid_input("Golomb-Rice parameter must be less than 64"));
+        }
+
+        let quotient = self.read_run(1, u64::MAX >> k)?;
+        Ok(quotient << k | self.read_bit_string(k)?)
+    }
+
+    pub fn read_exp_golomb(&mut self, k: usize) -> Result<u64, std::io::Error> {
+        if k >= 64 {
+            return Err(invalid_input("Exp-Golomb order must be less than 64"));
+        }
+
+        let zeros = self.read_run(0, (63 - k) as u64)? as usize;
+        let shifted = 1 << (zeros + k) | self.read_bit_string(zeros + k)?;
+        Ok(shifted - (1 << k))
+    }
+
+    pub fn read_leb128(&mut self) -> Result<u64, std::io::Error> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_bits(8)?;
+            if shift == 63 && byte > 1 {
+                break;  // Does not fit into u64
+            }
+
+            value |= (byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(invalid_code("LEB128 value is too long"))
+    }
+}
+
+// Byte-level access, used for byte-aligned data such as container headers
+impl<R: Read> Read for BitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        let bytes = self.read_bit_sequence(buf.len() * 8)?;
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        Ok(bytes.len())
+    }
+}
+
+impl<W: Write> Write for BitWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        self.write_bit_sequence(buf, buf.len() * 8)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        BitWriter::flush(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pseudo-random bit sequences of 1..=40 bits, unused bits of the last byte are cleared
+    fn sample_sequences(bit_order: BitOrder) -> Vec<(Vec<u8>, usize)> {
+        let mut seed = 0x9E3779B97F4A7C15u64;
+        (0..3000).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+
+            let bit_len = (seed % 40 + 1) as usize;
+            let mut bytes = seed.to_le_bytes()[..bit_len.div_ceil(8)].to_vec();
+            if !bit_len.is_multiple_of(8) {
+                let last_id = bytes.len() - 1;
+                bytes[last_id] &= match bit_order {
+                    BitOrder::LSBFirst => (1u8 << (bit_len % 8)) - 1,
+                    BitOrder::MSBFirst => !(0xFFu8 >> (bit_len % 8)),
+                };
+            }
+            (bytes, bit_len)
+        }).collect()
+    }
+
+    fn bit_at(bytes: &[u8], position: usize, bit_order: BitOrder) -> u8 {
+        match bit_order {
+            BitOrder::LSBFirst => (bytes[position / 8] >> (position % 8)) & 1,
+            BitOrder::MSBFirst => (bytes[position / 8] >> (7 - position % 8)) & 1,
+        }
+    }
+
+    fn write_all_sequences(sequences: &[(Vec<u8>, usize)], bit_order: BitOrder) -> Vec<u8> {
+        let mut writer = BitWriter::with_bit_order(Vec::new(), bit_order);
+        for (bytes, bit_len) in sequences {
+            writer.write_bit_sequence(bytes, *bit_len).unwrap();
+        }
+        writer.into_inner().unwrap()
+    }
+
+    // Returns at most 3 bytes per read to split sequences between buffer refills
+    struct SlowReader<'a>(&'a [u8]);
+
+    impl Read for SlowReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+            let n = buf.len().min(3).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn known_packing() {
+        let mut lsb = BitWriter::new(Vec::new());
+        lsb.write_bit_sequence(&[0b101], 3).unwrap();
+        lsb.write_bit_sequence(&[0b11111], 5).unwrap();
+        lsb.write_bit_sequence(&[0b11], 2).unwrap();
+        assert_eq!(lsb.into_inner().unwrap(), vec![0b11111101, 0b11]);
+
+        let mut msb = BitWriter::with_bit_order(Vec::new(), BitOrder::MSBFirst);
+        msb.write_bit_sequence(&[0b10100000], 3).unwrap();
+        msb.write_bit_sequence(&[0b11111000], 5).unwrap();
+        msb.write_bit_sequence(&[0b11000000], 2).unwrap();
+        assert_eq!(msb.into_inner().unwrap(), vec![0b10111111, 0b11000000]);
+    }
+
+    #[test]
+    fn round_trip_in_both_orders() {
+        for bit_order in [BitOrder::LSBFirst, BitOrder::MSBFirst] {
+            let sequences = sample_sequences(bit_order);
+            let total_bits: usize = sequences.iter().map(|(_, bit_len)| bit_len).sum();
+
+            let written = write_all_sequences(&sequences, bit_order);
+            assert_eq!(written.len(), total_bits.div_ceil(8));
+
+            let mut reader = BitReader::with_bit_order(written.as_slice(), bit_order);
+            let mut slow_reader = BitReader::with_bit_order(SlowReader(&written), bit_order);
+            for (bytes, bit_len) in sequences.iter() {
+                assert_eq!(reader.read_bit_sequence(*bit_len).unwrap(), *bytes);
+                assert_eq!(slow_reader.read_bit_sequence(*bit_len).unwrap(), *bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn stream_bits_follow_bit_order() {
+        for bit_order in [BitOrder::LSBFirst, BitOrder::MSBFirst] {
+            let sequences = sample_sequences(bit_order);
+            let written = write_all_sequences(&sequences, bit_order);
+
+            let mut position = 0;
+            for (bytes, bit_len) in sequences.iter() {
+                for i in 0..*bit_len {
+                    assert_eq!(bit_at(&written, position, bit_order), bit_at(bytes, i, bit_order));
+                    position += 1;
                 }
             }
+        }
+    }
+
+    #[test]
+    fn cross_order_reads_reverse_bits_within_bytes() {
+        let sequences = sample_sequences(BitOrder::MSBFirst);
+        let written = write_all_sequences(&sequences, BitOrder::MSBFirst);
 
-            bits_read += bits_to_move;
-            self.bit_pointer += bits_to_move;
+        // Whole bytes are the same in both orders, single bits are taken from opposite ends of a byte
+        let mut lsb_reader = BitReader::new(written.as_slice());
+        assert_eq!(lsb_reader.read_bit_sequence(written.len() * 8).unwrap(), written);
+
+        let mut lsb_reader = BitReader::new(written.as_slice());
+        let mut msb_reader = BitReader::with_bit_order(written.as_slice(), BitOrder::MSBFirst);
+        for position in 0..written.len() * 8 {
+            let lsb_bit = lsb_reader.read_bit_sequence(1).unwrap()[0];
+            let msb_bit = msb_reader.read_bit_sequence(1).unwrap()[0] >> 7;
+
+            assert_eq!(lsb_bit, bit_at(&written, position, BitOrder::LSBFirst));
+            assert_eq!(msb_bit, bit_at(&written, position, BitOrder::MSBFirst));
         }
 
-        Ok(result)
+        // Stream written LSB-first and read MSB-first gives bytes with reversed bits
+        let lsb_written = write_all_sequences(&sample_sequences(BitOrder::LSBFirst), BitOrder::LSBFirst);
+        let mut reversed = lsb_written.clone();
+        reverse_bits_in_place(&mut reversed);
+
+        let mut msb_reader = BitReader::with_bit_order(reversed.as_slice(), BitOrder::MSBFirst);
+        let mut lsb_reader = BitReader::new(lsb_written.as_slice());
+        for (bytes, bit_len) in sample_sequences(BitOrder::LSBFirst) {
+            let mut expected = bytes.clone();
+            reverse_bits_in_place(&mut expected);
+
+            assert_eq!(lsb_reader.read_bit_sequence(bit_len).unwrap(), bytes);
+            assert_eq!(msb_reader.read_bit_sequence(bit_len).unwrap(), expected);
+        }
     }
 
-    pub fn rewind_read_stream(&mut self) -> Result<(), std::io::Error> {
-        if !self.read_dir {
-            return create_error("Cannot reset stream in write mode");
+    #[test]
+    fn flush_pads_last_byte_with_zeros() {
+        for (bit_order, expected) in [(BitOrder::LSBFirst, 0b0000_0111), (BitOrder::MSBFirst, 0b1110_0000)] {
+            let mut writer = BitWriter::with_bit_order(Vec::new(), bit_order);
+            writer.write_bit_sequence(&[0xFF], 3).unwrap();
+            writer.flush().unwrap();
+            writer.write_bit_sequence(&[0xAB], 8).unwrap();
+            assert_eq!(writer.into_inner().unwrap(), vec![expected, 0xAB]);
         }
+    }
 
-        self.file.rewind()?;
-        self.buff.clear();
-        self.buff.resize(BUFF_MAX_BYTE_SIZE, 0u8);
-        
-        self.bit_pointer = 0;
-        self.byte_chunk_size = 0;
+    #[test]
+    fn partial_read_at_end_of_stream() {
+        for bit_order in [BitOrder::LSBFirst, BitOrder::MSBFirst] {
+            let mut reader = BitReader::with_bit_order([0x5Au8, 0xC3].as_slice(), bit_order);
+            assert_eq!(reader.read_bit_sequence(4).unwrap().len(), 1);
+            assert_eq!(reader.read_bit_sequence(20).unwrap().len(), 2);
+            assert!(reader.read_bit_sequence(1).unwrap().is_empty());
+        }
+    }
 
-        Ok(())
+    #[test]
+    fn integer_bits_layout() {
+        let mut lsb = BitWriter::new(Vec::new());
+        lsb.write_bits(0b101, 3).unwrap();
+        lsb.write_bits(0b11, 2).unwrap();
+        assert_eq!(lsb.into_inner().unwrap(), vec![0b00011101]);
+
+        let mut msb = BitWriter::with_bit_order(Vec::new(), BitOrder::MSBFirst);
+        msb.write_bits(0b101, 3).unwrap();
+        msb.write_bits(0b01, 2).unwrap();
+        msb.write_bits(0x1FF, 9).unwrap();
+        assert_eq!(msb.into_inner().unwrap(), vec![0b10101111, 0b11111100]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn integer_bits_round_trip() {
+        for bit_order in [BitOrder::LSBFirst, BitOrder::MSBFirst] {
+            let mut seed = 0x2545F4914F6CDD1Du64;
+            let values: Vec<(u64, usize)> = (0..5000).map(|i| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+
+                let bit_len = i % (MAX_BITS_PER_CALL + 1);
+                (seed & low_bits_mask(bit_len), bit_len)
+            }).collect();
+
+            let mut writer = BitWriter::with_bit_order(Vec::new(), bit_order);
+            for &(value, bit_len) in values.iter() {
+                writer.write_bits(value, bit_len).unwrap();
+            }
+            let written = writer.into_inner().unwrap();
+
+            let mut reader = BitReader::with_bit_order(SlowReader(&written), bit_order);
+            for &(value, bit_len) in values.iter() {
+                assert_eq!(reader.peek_bits(bit_len).unwrap(), value);
+                assert_eq!(reader.read_bits(bit_len).unwrap(), value);
+            }
+        }
+    }
+
+    #[test]
+    fn integer_and_sequence_apis_agree() {
+        for bit_order in [BitOrder::LSBFirst, BitOrder::MSBFirst] {
+            let sequences = sample_sequences(bit_order);
+            let written = write_all_sequences(&sequences, bit_order);
+
+            let mut reader = BitReader::with_bit_order(written.as_slice(), bit_order);
+            for (bytes, bit_len) in sequences.iter() {
+                let mut writer = BitWriter::with_bit_order(Vec::new(), bit_order);
+                writer.write_bits(reader.read_bits(*bit_len).unwrap(), *bit_len).unwrap();
+                assert_eq!(writer.into_inner().unwrap(), *bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn peek_consume_and_position() {
+        let data = [0b10110011u8, 0xF0, 0x0F];
+        let mut reader = BitReader::with_bit_order(data.as_slice(), BitOrder::MSBFirst);
+
+        assert_eq!(reader.peek_bits(4).unwrap(), 0b1011);
+        assert_eq!(reader.peek_bits(4).unwrap(), 0b1011);
+        assert_eq!(reader.bit_position(), 0);
+
+        reader.consume(3).unwrap();
+        assert_eq!(reader.bit_position(), 3);
+        assert_eq!(reader.read_bits(2).unwrap(), 0b10);
+
+        reader.align_to_byte().unwrap();
+        assert_eq!(reader.bit_position(), 8);
+        reader.align_to_byte().unwrap();
+        assert_eq!(reader.read_bits(8).unwrap(), 0xF0);
+
+        // Missing bits are zeros for peeking, but cannot be read
+        assert_eq!(reader.peek_bits(12).unwrap(), 0x0F0);
+        assert_eq!(reader.read_bits(12).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(reader.read_bits(8).unwrap(), 0x0F);
+        assert!(reader.consume(1).is_err());
+        assert!(reader.peek_bits(65).is_err());
+    }
+
+    #[test]
+    fn writer_align_and_position() {
+        let mut writer = BitWriter::new(Vec::new());
+        writer.write_bits(0b1, 1).unwrap();
+        assert_eq!(writer.bit_position(), 1);
+
+        writer.align_to_byte();
+        writer.align_to_byte();
+        assert_eq!(writer.bit_position(), 8);
+
+        writer.write_bits(0xABCD, 16).unwrap();
+        writer.flush().unwrap();
+        writer.write_bits(0b11, 2).unwrap();
+        assert_eq!(writer.bit_position(), 26);
+        assert_eq!(writer.into_inner().unwrap(), vec![0x01, 0xCD, 0xAB, 0x03]);
+    }
+
+    #[test]
+    fn reader_seek_bits() {
+        for bit_order in [BitOrder::LSBFirst, BitOrder::MSBFirst] {
+            // Large enough for seeks outside of the buffer
+            let values: Vec<u64> = (0..20000u64).map(|i| i * 2654435761 % 8191).collect();
+            let mut writer = BitWriter::with_bit_order(Vec::new(), bit_order);
+            for &value in values.iter() {
+                writer.write_bits(value, 13).unwrap();
+            }
+            let written = writer.into_inner().unwrap();
+
+            let mut reader = BitReader::with_bit_order(std::io::Cursor::new(written), bit_order);
+            for &value_id in [19999usize, 0, 7, 10000, 10001, 10000, 3, 19998, 5000].iter() {
+                reader.seek_bits(value_id as u64 * 13).unwrap();
+                assert_eq!(reader.bit_position(), value_id as u64 * 13);
+                assert_eq!(reader.read_bits(13).unwrap(), values[value_id]);
+            }
+
+            reader.seek_bits(20000 * 13).unwrap();
+            assert!(reader.read_bits(8).is_err());
+        }
+    }
+
+    #[test]
+    fn writer_patch_bits() {
+        for bit_order in [BitOrder::LSBFirst, BitOrder::MSBFirst] {
+            let mut writer = BitWriter::with_bit_order(std::io::Cursor::new(Vec::new()), bit_order);
+
+            // Placeholders: one passed to the sink before patching, one still buffered
+            writer.write_bits(0b101, 3).unwrap();
+            let early_field = writer.bit_position();
+            writer.write_bits(0, 20).unwrap();
+            for i in 0..5000u64 {
+                writer.write_bits(i, 11).unwrap();
+            }
+            let late_field = writer.bit_position();
+            writer.write_bits(0, 30).unwrap();
+            writer.write_bits(0b1, 1).unwrap();
+
+            writer.patch_bits(early_field, 0xABCDE, 20).unwrap();
+            writer.patch_bits(late_field, 0x2345_6789, 30).unwrap();
+            assert!(writer.patch_bits(late_field + 2, 0, 30).is_err());
+
+            writer.write_bits(0x7F, 7).unwrap();
+            let written = writer.into_inner().unwrap().into_inner();
+
+            let mut reader = BitReader::with_bit_order(written.as_slice(), bit_order);
+            assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+            assert_eq!(reader.read_bits(20).unwrap(), 0xABCDE);
+            for i in 0..5000u64 {
+                assert_eq!(reader.read_bits(11).unwrap(), i % 2048);
+            }
+            assert_eq!(reader.read_bits(30).unwrap(), 0x2345_6789);
+            assert_eq!(reader.read_bits(1).unwrap(), 0b1);
+            assert_eq!(reader.read_bits(7).unwrap(), 0x7F);
+        }
+    }
+
+    // Writes a single code to an MSB-first stream and returns it as a string of bits
+    fn code_bits(write: impl Fn(&mut BitWriter<Vec<u8>>) -> Result<(), std::io::Error>) -> String {
+        let mut writer = BitWriter::with_bit_order(Vec::new(), BitOrder::MSBFirst);
+        write(&mut writer).unwrap();
+        let bit_len = writer.bit_position() as usize;
+
+        let bits = bin_string_MSBF(&writer.into_inner().unwrap()).replace(' ', "");
+        bits[..bit_len].to_string()
+    }
+
+    #[test]
+    fn universal_codes_known_values() {
+        assert_eq!(code_bits(|w| w.write_elias_gamma(1)), "1");
+        assert_eq!(code_bits(|w| w.write_elias_gamma(5)), "00101");
+        assert_eq!(code_bits(|w| w.write_elias_delta(1)), "1");
+        assert_eq!(code_bits(|w| w.write_elias_delta(10)), "00100010");
+        assert_eq!(code_bits(|w| w.write_fibonacci(1)), "11");
+        assert_eq!(code_bits(|w| w.write_fibonacci(4)), "1011");
+        assert_eq!(code_bits(|w| w.write_fibonacci(12)), "101011");
+        assert_eq!(code_bits(|w| w.write_golomb_rice(9, 2)), "11001");
+        assert_eq!(code_bits(|w| w.write_golomb_rice(3, 0)), "1110");
+        assert_eq!(code_bits(|w| w.write_exp_golomb(0, 0)), "1");
+        assert_eq!(code_bits(|w| w.write_exp_golomb(3, 0)), "00100");
+        assert_eq!(code_bits(|w| w.write_exp_golomb(3, 2)), "111");
+        assert_eq!(code_bits(|w| w.write_exp_golomb(4, 2)), "01000");
+        assert_eq!(code_bits(|w| w.write_leb128(624485)), "111001011000111000100110");
+
+        assert_eq!(code_bits(|w| w.write_leb128(0)), "00000000");
+        assert!(BitWriter::new(Vec::new()).write_elias_gamma(0).is_err());
+        assert!(BitWriter::new(Vec::new()).write_fibonacci(0).is_err());
+        assert!(BitWriter::new(Vec::new()).write_exp_golomb(u64::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn universal_codes_round_trip() {
+        let mut values: Vec<u64> = (1..300).collect();
+        values.extend((0..64).map(|shift| 1u64 << shift));
+        values.extend((1..64).map(|shift| (1u64 << shift) - 1));
+        values.extend([u64::MAX, u64::MAX - 1, 12200160415121876738]);
+
+        for bit_order in [BitOrder::LSBFirst, BitOrder::MSBFirst] {
+            let mut writer = BitWriter::with_bit_order(Vec::new(), bit_order);
+            for &value in values.iter() {
+                writer.write_elias_gamma(value).unwrap();
+                writer.write_elias_delta(value).unwrap();
+                writer.write_fibonacci(value).unwrap();
+                writer.write_golomb_rice(value.min(5000), 3).unwrap();
+                writer.write_golomb_rice(value, 60).unwrap();
+                writer.write_exp_golomb(value - 1, 0).unwrap();
+                writer.write_exp_golomb(value >> 5, 5).unwrap();
+                writer.write_leb128(value).unwrap();
+                writer.write_bits(1, 1).unwrap();   // Keeps codes unaligned
+            }
+            let written = writer.into_inner().unwrap();
+
+            let mut reader = BitReader::with_bit_order(written.as_slice(), bit_order);
+            for &value in values.iter() {
+                assert_eq!(reader.read_elias_gamma().unwrap(), value);
+                assert_eq!(reader.read_elias_delta().unwrap(), value);
+                assert_eq!(reader.read_fibonacci().unwrap(), value);
+                assert_eq!(reader.read_golomb_rice(3).unwrap(), value.min(5000));
+                assert_eq!(reader.read_golomb_rice(60).unwrap(), value);
+                assert_eq!(reader.read_exp_golomb(0).unwrap(), value - 1);
+                assert_eq!(reader.read_exp_golomb(5).unwrap(), value >> 5);
+                assert_eq!(reader.read_leb128().unwrap(), value);
+                assert_eq!(reader.read_bits(1).unwrap(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn universal_codes_reject_damaged_input() {
+        let zeros = [0u8; 16];
+        assert!(BitReader::new(zeros.as_slice()).read_elias_gamma().is_err());
+        assert!(BitReader::new(zeros.as_slice()).read_exp_golomb(0).is_err());
+        assert!(BitReader::new(zeros.as_slice()).read_fibonacci().is_err());
+
+        let ones = [0xFFu8; 16];
+        assert!(BitReader::new(ones.as_slice()).read_leb128().is_err());
+        assert!(BitReader::new(ones.as_slice()).read_golomb_rice(0).is_err());
+
+        // Fibonacci code longer than any u64 value
+        let mut writer = BitWriter::new(Vec::new());
+        for _ in 0..50 {
+            writer.write_bits(0b01, 2).unwrap();
+        }
+        writer.write_bits(0b11, 2).unwrap();
+        let written = writer.into_inner().unwrap();
+        assert_eq!(BitReader::new(written.as_slice()).read_fibonacci().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+}
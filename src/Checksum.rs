@@ -0,0 +1,409 @@
+use std::io::{Read, Write};
+
+use crate::Error::Error;
+
+// Size of data covered by one block checksum (64KB)
+pub const CHECKSUM_BLOCK_SIZE: usize = 65536;
+
+// Layout of checksum bits in the container header flags
+const FLAG_KIND_MASK: u8 = 0b011;
+const FLAG_PER_BLOCK: u8 = 0b100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumKind {
+    None,
+    CRC32,
+    Adler32,
+    XXH64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChecksumSettings {
+    pub kind: ChecksumKind,
+    pub per_block: bool,     // Additionally store a checksum for every CHECKSUM_BLOCK_SIZE bytes
+}
+
+impl ChecksumSettings {
+    pub const NONE: ChecksumSettings = ChecksumSettings { kind: ChecksumKind::None, per_block: false };
+
+    pub fn new(kind: ChecksumKind, per_block: bool) -> Self {
+        ChecksumSettings { kind, per_block }
+    }
+
+    pub fn from_flags(flags: u8) -> Self {
+        let kind = match flags & FLAG_KIND_MASK {
+            1 => ChecksumKind::CRC32,
+            2 => ChecksumKind::Adler32,
+            3 => ChecksumKind::XXH64,
+            _ => ChecksumKind::None,
+        };
+
+        ChecksumSettings { kind, per_block: kind != ChecksumKind::None && flags & FLAG_PER_BLOCK != 0 }
+    }
+
+    pub fn to_flags(self) -> u8 {
+        let kind_bits = match self.kind {
+            ChecksumKind::None => return 0,
+            ChecksumKind::CRC32 => 1,
+            ChecksumKind::Adler32 => 2,
+            ChecksumKind::XXH64 => 3,
+        };
+
+        if self.per_block { kind_bits | FLAG_PER_BLOCK } else { kind_bits }
+    }
+
+    fn digest_size(&self) -> usize {
+        match self.kind {
+            ChecksumKind::None => 0,
+            ChecksumKind::CRC32 | ChecksumKind::Adler32 => 4,
+            ChecksumKind::XXH64 => 8,
+        }
+    }
+
+    // Trailer holds the stream checksum followed by block checksums
+    pub fn trailer_size(&self, original_size: u64) -> u64 {
+        let blocks = if self.per_block { original_size.div_ceil(CHECKSUM_BLOCK_SIZE as u64) } else { 0 };
+        (1 + blocks) * self.digest_size() as u64
+    }
+}
+
+fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut crc = i as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+        *entry = crc;
+    }
+    table
+}
+
+const XXH_PRIME_1: u64 = 0x9E37_79B1_85EB_CA87;
+const XXH_PRIME_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const XXH_PRIME_3: u64 = 0x1656_67B1_9E37_79F9;
+const XXH_PRIME_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const XXH_PRIME_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+fn xxh64_round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(XXH_PRIME_2)).rotate_left(31).wrapping_mul(XXH_PRIME_1)
+}
+
+fn xxh64_merge_round(acc: u64, value: u64) -> u64 {
+    (acc ^ xxh64_round(0, value)).wrapping_mul(XXH_PRIME_1).wrapping_add(XXH_PRIME_4)
+}
+
+// Incremental checksum of a single stream
+#[derive(Clone)]
+pub struct Hasher {
+    kind: ChecksumKind,
+    crc_table: Option<Box<[u32; 256]>>,
+    state: [u64; 4],            // CRC / Adler (a, b) / xxHash accumulators
+    buffer: Vec<u8>,            // Unprocessed xxHash stripe
+    total_len: u64,
+}
+
+impl Hasher {
+    pub fn new(kind: ChecksumKind) -> Self {
+        let state = match kind {
+            ChecksumKind::CRC32 => [0xFFFF_FFFF, 0, 0, 0],
+            ChecksumKind::Adler32 => [1, 0, 0, 0],
+            ChecksumKind::XXH64 => [
+                XXH_PRIME_1.wrapping_add(XXH_PRIME_2),
+                XXH_PRIME_2,
+                0,
+                0u64.wrapping_sub(XXH_PRIME_1),
+            ],
+            ChecksumKind::None => [0; 4],
+        };
+
+        Hasher {
+            kind,
+            crc_table: if kind == ChecksumKind::CRC32 { Some(Box::new(crc32_table())) } else { None },
+            state,
+            buffer: Vec::with_capacity(32),
+            total_len: 0,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.total_len += data.len() as u64;
+
+        match self.kind {
+            ChecksumKind::None => {}
+            ChecksumKind::CRC32 => {
+                let table = self.crc_table.as_ref().unwrap();
+                let mut crc = self.state[0] as u32;
+                for &byte in data {
+                    crc = table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+                }
+                self.state[0] = crc as u64;
+            }
+            ChecksumKind::Adler32 => {
+                // Reduce modulo rarely: 5552 is the largest run that cannot overflow u32 sums
+                for chunk in data.chunks(5552) {
+                    let (mut a, mut b) = (self.state[0], self.state[1]);
+                    for &byte in chunk {
+                        a += byte as u64;
+                        b += a;
+                    }
+                    self.state[0] = a % 65521;
+                    self.state[1] = b % 65521;
+                }
+            }
+            ChecksumKind::XXH64 => {
+                let mut data = data;
+                if !self.buffer.is_empty() {
+                    let to_fill = (32 - self.buffer.len()).min(data.len());
+                    self.buffer.extend_from_slice(&data[..to_fill]);
+                    data = &data[to_fill..];
+
+                    if self.buffer.len() < 32 {
+                        return;
+                    }
+                    let stripe = std::mem::take(&mut self.buffer);
+                    self.process_stripe(&stripe);
+                    self.buffer = stripe;
+                    self.buffer.clear();
+                }
+
+                let mut stripes = data.chunks_exact(32);
+                for stripe in &mut stripes {
+                    self.process_stripe(stripe);
+                }
+                self.buffer.extend_from_slice(stripes.remainder());
+            }
+        }
+    }
+
+    fn process_stripe(&mut self, stripe: &[u8]) {
+        for (lane, acc) in self.state.iter_mut().enumerate() {
+            let input = u64::from_le_bytes(stripe[lane * 8..lane * 8 + 8].try_into().unwrap());
+            *acc = xxh64_round(*acc, input);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        match self.kind {
+            ChecksumKind::None => 0,
+            ChecksumKind::CRC32 => self.state[0] ^ 0xFFFF_FFFF,
+            ChecksumKind::Adler32 => (self.state[1] << 16) | self.state[0],
+            ChecksumKind::XXH64 => {
+                let [v1, v2, v3, v4] = self.state;
+                let mut hash = if self.total_len >= 32 {
+                    let mut hash = v1.rotate_left(1)
+                                     .wrapping_add(v2.rotate_left(7))
+                                     .wrapping_add(v3.rotate_left(12))
+                                     .wrapping_add(v4.rotate_left(18));
+                    for v in self.state {
+                        hash = xxh64_merge_round(hash, v);
+                    }
+                    hash
+                } else {
+                    XXH_PRIME_5
+                };
+                hash = hash.wrapping_add(self.total_len);
+
+                let mut tail = self.buffer.chunks_exact(8);
+                for lane in &mut tail {
+                    let k1 = xxh64_round(0, u64::from_le_bytes(lane.try_into().unwrap()));
+                    hash = (hash ^ k1).rotate_left(27).wrapping_mul(XXH_PRIME_1).wrapping_add(XXH_PRIME_4);
+                }
+
+                let mut tail = tail.remainder();
+                if tail.len() >= 4 {
+                    let k1 = u32::from_le_bytes(tail[..4].try_into().unwrap()) as u64;
+                    hash = (hash ^ k1.wrapping_mul(XXH_PRIME_1)).rotate_left(23).wrapping_mul(XXH_PRIME_2).wrapping_add(XXH_PRIME_3);
+                    tail = &tail[4..];
+                }
+                for &byte in tail {
+                    hash = (hash ^ (byte as u64).wrapping_mul(XXH_PRIME_5)).rotate_left(11).wrapping_mul(XXH_PRIME_1);
+                }
+
+                hash ^= hash >> 33;
+                hash = hash.wrapping_mul(XXH_PRIME_2);
+                hash ^= hash >> 29;
+                hash = hash.wrapping_mul(XXH_PRIME_3);
+                hash ^ (hash >> 32)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChecksumTrailer {
+    pub stream: u64,
+    pub blocks: Vec<u64>,
+}
+
+// Computes stream and block checksums while data is passed through it
+pub struct StreamChecksum {
+    settings: ChecksumSettings,
+    stream: Hasher,
+    block: Hasher,
+    block_fill: usize,
+    blocks: Vec<u64>,
+}
+
+impl StreamChecksum {
+    pub fn new(settings: ChecksumSettings) -> Self {
+        StreamChecksum {
+            settings,
+            stream: Hasher::new(settings.kind),
+            block: Hasher::new(settings.kind),
+            block_fill: 0,
+            blocks: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.settings.kind == ChecksumKind::None {
+            return;
+        }
+
+        self.stream.update(data);
+
+        while self.settings.per_block && !data.is_empty() {
+            let to_take = (CHECKSUM_BLOCK_SIZE - self.block_fill).min(data.len());
+            self.block.update(&data[..to_take]);
+            self.block_fill += to_take;
+            data = &data[to_take..];
+
+            if self.block_fill == CHECKSUM_BLOCK_SIZE {
+                self.blocks.push(self.block.finish());
+                self.block = Hasher::new(self.settings.kind);
+                self.block_fill = 0;
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> ChecksumTrailer {
+        if self.block_fill != 0 {
+            self.blocks.push(self.block.finish());
+        }
+
+        ChecksumTrailer { stream: self.stream.finish(), blocks: self.blocks }
+    }
+}
+
+impl ChecksumTrailer {
+    pub fn write_to<W: Write>(&self, writer: &mut W, settings: ChecksumSettings) -> Result<(), std::io::Error> {
+        let digest_size = settings.digest_size();
+        for checksum in std::iter::once(&self.stream).chain(self.blocks.iter()) {
+            writer.write_all(&checksum.to_le_bytes()[..digest_size])?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R, settings: ChecksumSettings, original_size: u64) -> Result<Self, std::io::Error> {
+        let digest_size = settings.digest_size();
+        if digest_size == 0 {
+            return Ok(ChecksumTrailer { stream: Hasher::new(settings.kind).finish(), blocks: Vec::new() });
+        }
+
+        // Size comes from the header, so the buffer only grows with data actually present
+        let trailer_size = settings.trailer_size(original_size);
+        let mut bytes = Vec::new();
+        reader.take(trailer_size).read_to_end(&mut bytes)?;
+        if (bytes.len() as u64) < trailer_size {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let mut checksums = bytes.chunks_exact(digest_size).map(|digest| {
+            let mut value = [0u8; 8];
+            value[..digest_size].copy_from_slice(digest);
+            u64::from_le_bytes(value)
+        });
+
+        Ok(ChecksumTrailer { stream: checksums.next().unwrap_or(0), blocks: checksums.collect() })
+    }
+}
+
+fn checksum_mismatch(message: String) -> Result<(), Error> {
+    Err(Error::ChecksumMismatch(message))
+}
+
+// Compares decoded data checksums with stored ones, reports the position of the first damaged block
+pub fn verify(expected: &ChecksumTrailer, actual: &ChecksumTrailer) -> Result<(), Error> {
+    for (block_id, (expected_block, actual_block)) in expected.blocks.iter().zip(actual.blocks.iter()).enumerate() {
+        if expected_block != actual_block {
+            return checksum_mismatch(format!("Checksum mismatch in block {} (bytes {}..{}): expected {:#x}, got {:#x}",
+                                             block_id, block_id * CHECKSUM_BLOCK_SIZE, (block_id + 1) * CHECKSUM_BLOCK_SIZE,
+                                             expected_block, actual_block));
+        }
+    }
+
+    if expected.blocks.len() != actual.blocks.len() {
+        return checksum_mismatch(format!("Checksum mismatch: expected {} blocks, decoded {}", expected.blocks.len(), actual.blocks.len()));
+    }
+
+    if expected.stream != actual.stream {
+        return checksum_mismatch(format!("Checksum mismatch for the whole stream: expected {:#x}, got {:#x}", expected.stream, actual.stream));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checksum(kind: ChecksumKind, data: &[u8]) -> u64 {
+        let mut hasher = Hasher::new(kind);
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    #[test]
+    fn known_answers() {
+        assert_eq!(checksum(ChecksumKind::CRC32, b"123456789"), 0xCBF4_3926);
+        assert_eq!(checksum(ChecksumKind::CRC32, b""), 0);
+        assert_eq!(checksum(ChecksumKind::Adler32, b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(checksum(ChecksumKind::Adler32, b""), 1);
+        assert_eq!(checksum(ChecksumKind::XXH64, b""), 0xEF46_DB37_51D8_E999);
+        assert_eq!(checksum(ChecksumKind::XXH64, b"abc"), 0x44BC_2CF5_AD77_0999);
+    }
+
+    #[test]
+    fn update_in_pieces_matches_single_update() {
+        // All 0xFF bytes give the largest Adler sums, piece sizes cross 5552-byte runs and 32-byte stripes
+        let mut data = vec![0xFF; 3 * 5552 + 100];
+        data.extend((0..1000).map(|i| (i * 31 % 256) as u8));
+        let pieces = [1, 31, 5551, 2, 5552, 33, 64, 7, 5553, 0, 32, 17];
+
+        for kind in [ChecksumKind::CRC32, ChecksumKind::Adler32, ChecksumKind::XXH64] {
+            let mut hasher = Hasher::new(kind);
+            let mut rest = &data[..];
+            for &size in pieces.iter().cycle() {
+                if rest.is_empty() {
+                    break;
+                }
+                let (piece, tail) = rest.split_at(size.min(rest.len()));
+                hasher.update(piece);
+                rest = tail;
+            }
+            assert_eq!(hasher.finish(), checksum(kind, &data), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn block_checksums_cover_fixed_blocks() {
+        let data: Vec<u8> = (0..2 * CHECKSUM_BLOCK_SIZE + 10).map(|i| (i % 253) as u8).collect();
+        let settings = ChecksumSettings::new(ChecksumKind::CRC32, true);
+
+        let mut stream = StreamChecksum::new(settings);
+        for chunk in data.chunks(1000) {
+            stream.update(chunk);
+        }
+        let trailer = stream.finish();
+
+        let expected_blocks: Vec<u64> = data.chunks(CHECKSUM_BLOCK_SIZE).map(|block| checksum(ChecksumKind::CRC32, block)).collect();
+        assert_eq!(trailer.blocks, expected_blocks);
+        assert_eq!(trailer.stream, checksum(ChecksumKind::CRC32, &data));
+
+        let mut stored = Vec::new();
+        trailer.write_to(&mut stored, settings).unwrap();
+        assert_eq!(stored.len() as u64, settings.trailer_size(data.len() as u64));
+        assert_eq!(ChecksumTrailer::read_from(&mut &stored[..], settings, data.len() as u64).unwrap(), trailer);
+    }
+}
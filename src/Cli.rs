@@ -0,0 +1,863 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use Lab5::Archive::{self, ArchiveSettings, ARCHIVE_MAGIC};
+use Lab5::Checksum::{ChecksumKind, ChecksumSettings};
+use Lab5::Codec::{find_codec, find_codec_by_name, registered_codecs};
+use Lab5::Container::{self, CODEC_HUFFMAN, DEFAULT_MAX_OUTPUT_SIZE, MAGIC, MAX_PIPELINE_STAGES, UNKNOWN_SIZE};
+use Lab5::Error::Error;
+use Lab5::Seekable::{self, SEEKABLE_MAGIC};
+use Lab5::SpillBuffer::SpillBuffer;
+use Lab5::MethodSelection;
+use Lab5::TransformationMethods::{preset_pipelines, transform_pipeline, TransformStage};
+
+use crate::Benchmark;
+
+const USAGE: &str = "\
+Usage: Lab5 <command> [options] <input>
+       Lab5 archive [options] <input>...
+       Lab5 add [options] <archive> <input>...
+
+Use - as input or output path for stdin or stdout, e.g. `tar c dir | Lab5 compress - > dir.tar.bsl`
+
+Commands:
+  compress     Compress a file
+  decompress   Decompress a stream, seekable stream or archive
+  test         Verify a compressed file, or check that a plain file survives a round-trip
+  info         Show header of a compressed file
+  list         List entries of an archive
+  archive      Pack files and directories into an archive
+  add          Add files and directories to an existing archive
+  bench        Compare codecs and transform presets on one or more files
+
+Options:
+  -o, --output <path>       Output path (default: <input>.bsl, or input without .bsl for decompress;
+                            stdout when input is stdin; <first input>.bsla for archive)
+  -c, --codec <name>        One of the codecs listed below (default: huffman)
+  -t, --transform <list>    Preset id (0-19) or comma-separated stages:
+                            bwt, bwts, mtf, mtf1, mtf2, wfc, if, dc, bcj, delta:<n>, deinterleave:<n>
+  -a, --auto                Pick codec and transform preset by trial compression of input samples,
+                            -c or -t fix that part of the choice
+  -b, --block-size <size>   Compress into independently decodable blocks (seekable format), e.g. 1M
+      --checksum <kind>     none, crc32, adler32 or xxh64 (default: crc32)
+      --no-block-checksums  Store only the checksum of the whole stream
+      --solid               Compress all archived files as one stream
+      --max-output <size>   Refuse to decompress streams larger than this (default: 4G)
+      --range <offset:len>  Decompress only this byte range of a seekable stream, e.g. 1M:4K
+      --csv <path>          Write benchmark results as CSV
+      --markdown <path>     Write benchmark results as Markdown tables
+  -h, --help                Show this help";
+
+// Extension of compressed files
+const DEFAULT_EXTENSION: &str = ".bsl";
+const ARCHIVE_EXTENSION: &str = ".bsla";
+
+// Path meaning stdin for input and stdout for output
+const STD_STREAM: &str = "-";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Compress,
+    Decompress,
+    Test,
+    Info,
+    List,
+    Archive,
+    Add,
+    Bench,
+}
+
+impl Command {
+    fn takes_many_inputs(self) -> bool {
+        matches!(self, Command::Archive | Command::Add | Command::Bench)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub command: Command,
+    pub input: String,
+    pub output: Option<String>,
+    pub codec_id: Option<u8>,
+    pub pipeline: Option<Vec<TransformStage>>,
+    pub block_size: Option<usize>,
+    pub checksum: ChecksumSettings,
+    pub max_output_size: u64,
+    pub range: Option<(u64, u64)>,  // Offset and length in decompressed data
+    pub auto: bool,
+    pub solid: bool,
+    pub inputs: Vec<String>,    // All inputs of archive, add and bench, other commands take only one
+    pub csv_report: Option<String>,
+    pub markdown_report: Option<String>,
+}
+
+impl Options {
+    fn codec_id(&self) -> u8 {
+        self.codec_id.unwrap_or(CODEC_HUFFMAN)
+    }
+
+    fn pipeline(&self) -> Vec<TransformStage> {
+        self.pipeline.clone().unwrap_or_default()
+    }
+}
+
+fn parse_codec(name: &str) -> Result<u8, String> {
+    find_codec_by_name(name).map(|codec| codec.id()).ok_or_else(|| format!("Unknown codec: {}", name))
+}
+
+pub fn codec_name(codec_id: u8) -> &'static str {
+    find_codec(codec_id).map_or("unknown", |codec| codec.name())
+}
+
+fn parse_stage(name: &str) -> Result<TransformStage, String> {
+    let (stage_name, parameter) = match name.split_once(':') {
+        Some((stage_name, parameter)) => {
+            let parameter: u8 = parameter.parse().map_err(|_| format!("Invalid parameter of transform stage: {}", name))?;
+            if parameter == 0 {
+                return Err(format!("Parameter of transform stage must be positive: {}", name));
+            }
+            (stage_name, Some(parameter))
+        }
+        None => (name, None),
+    };
+
+    let stage = match (stage_name.to_ascii_lowercase().as_str(), parameter) {
+        ("bwt", None) => TransformStage::BWT,
+        ("bwts", None) => TransformStage::BWTS,
+        ("mtf", None) => TransformStage::MTF,
+        ("mtf1", None) => TransformStage::MTF1,
+        ("mtf2", None) => TransformStage::MTF2,
+        ("wfc", None) => TransformStage::WFC,
+        ("if", None) => TransformStage::IF,
+        ("dc", None) => TransformStage::DC,
+        ("bcj", None) => TransformStage::BCJ,
+        ("delta", Some(stride)) => TransformStage::Delta(stride),
+        ("deinterleave", Some(channels)) => TransformStage::Deinterleave(channels),
+        _ => return Err(format!("Unknown transform stage: {}", name)),
+    };
+
+    Ok(stage)
+}
+
+// Accepts a preset id or a comma-separated list of stages
+fn parse_pipeline(value: &str) -> Result<Vec<TransformStage>, String> {
+    if let Ok(preset_id) = value.parse::<u8>() {
+        return transform_pipeline(preset_id).map_err(|_| format!("Unknown transform preset: {}", preset_id));
+    }
+
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(Vec::new());
+    }
+
+    let pipeline = value.split(',').map(|name| parse_stage(name.trim())).collect::<Result<Vec<_>, _>>()?;
+    if pipeline.len() > MAX_PIPELINE_STAGES {
+        return Err(format!("Pipeline has {} stages, at most {} are supported", pipeline.len(), MAX_PIPELINE_STAGES));
+    }
+    Ok(pipeline)
+}
+
+fn parse_checksum_kind(name: &str) -> Result<ChecksumKind, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "none" => Ok(ChecksumKind::None),
+        "crc32" => Ok(ChecksumKind::CRC32),
+        "adler32" => Ok(ChecksumKind::Adler32),
+        "xxh64" | "xxhash64" => Ok(ChecksumKind::XXH64),
+        _ => Err(format!("Unknown checksum: {}", name)),
+    }
+}
+
+// Size in bytes with optional K/M/G/T suffix
+fn parse_size(value: &str) -> Result<u64, String> {
+    let upper = value.to_ascii_uppercase();
+    let (digits, multiplier) = match upper.chars().last() {
+        Some('K') => (&upper[..upper.len() - 1], 1 << 10),
+        Some('M') => (&upper[..upper.len() - 1], 1 << 20),
+        Some('G') => (&upper[..upper.len() - 1], 1 << 30),
+        Some('T') => (&upper[..upper.len() - 1], 1 << 40),
+        _ => (upper.as_str(), 1),
+    };
+
+    digits.parse::<u64>().ok()
+          .and_then(|size| size.checked_mul(multiplier))
+          .ok_or_else(|| format!("Invalid size: {}", value))
+}
+
+fn parse_range(value: &str) -> Result<(u64, u64), String> {
+    let (offset, length) = value.split_once(':').ok_or_else(|| format!("Invalid range (expected <offset>:<length>): {}", value))?;
+    Ok((parse_size(offset)?, parse_size(length)?))
+}
+
+fn parse_block_size(value: &str) -> Result<usize, String> {
+    let size = parse_size(value)?;
+
+    // Frame sizes are stored as u32 in the seekable index
+    if size == 0 || size > u32::MAX as u64 {
+        return Err(format!("Block size must be between 1 byte and 4GB: {}", value));
+    }
+    Ok(size as usize)
+}
+
+// Returns None when help is requested
+pub fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        return Ok(None);
+    }
+
+    let command = match args[0].as_str() {
+        "compress" | "c" => Command::Compress,
+        "decompress" | "d" => Command::Decompress,
+        "test" | "t" => Command::Test,
+        "info" | "i" => Command::Info,
+        "list" | "l" => Command::List,
+        "archive" => Command::Archive,
+        "add" => Command::Add,
+        "bench" | "b" => Command::Bench,
+        other => return Err(format!("Unknown command: {}", other)),
+    };
+
+    let mut inputs: Vec<String> = Vec::new();
+    let mut csv_report = None;
+    let mut auto = false;
+    let mut solid = false;
+    let mut markdown_report = None;
+    let mut output = None;
+    let mut codec_id = None;
+    let mut pipeline = None;
+    let mut block_size = None;
+    let mut checksum = ChecksumSettings::new(ChecksumKind::CRC32, true);
+    let mut max_output_size = DEFAULT_MAX_OUTPUT_SIZE;
+    let mut range = None;
+
+    let mut args_iter = args[1..].iter();
+    while let Some(arg) = args_iter.next() {
+        let mut value = |option: &str| {
+            args_iter.next().cloned().ok_or_else(|| format!("Missing value for option {}", option))
+        };
+
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(value(arg)?),
+            "-c" | "--codec" => codec_id = Some(parse_codec(&value(arg)?)?),
+            "-t" | "--transform" => pipeline = Some(parse_pipeline(&value(arg)?)?),
+            "-a" | "--auto" => auto = true,
+            "-b" | "--block-size" => block_size = Some(parse_block_size(&value(arg)?)?),
+            "--checksum" => checksum.kind = parse_checksum_kind(&value(arg)?)?,
+            "--no-block-checksums" => checksum.per_block = false,
+            "--solid" => solid = true,
+            "--max-output" => max_output_size = parse_size(&value(arg)?)?,
+            "--range" => range = Some(parse_range(&value(arg)?)?),
+            "--csv" => csv_report = Some(value(arg)?),
+            "--markdown" => markdown_report = Some(value(arg)?),
+            option if option.starts_with('-') && option.len() > 1 => return Err(format!("Unknown option: {}", option)),
+            path => {
+                if !inputs.is_empty() && !command.takes_many_inputs() {
+                    return Err(format!("Unexpected argument: {}", path));
+                }
+                inputs.push(path.to_string());
+            }
+        }
+    }
+
+    let input = inputs.first().cloned().ok_or_else(|| "Missing input path".to_string())?;
+    Ok(Some(Options { command, input, output, codec_id, pipeline, block_size, checksum, max_output_size, range, auto, solid,
+                      inputs, csv_report, markdown_report }))
+}
+
+// Runs work in a separate thread and shows elapsed time while it is running.
+// Status goes to stderr, so stdout stays clean for piped data.
+fn run_with_timer<T: Send + 'static>(label: &str, work: impl FnOnce() -> T + Send + 'static) -> Result<(T, Duration), String> {
+    let start = Instant::now();
+    let handle = std::thread::spawn(work);
+
+    // Progress time
+    while !handle.is_finished() {
+        eprint!("\r{} time: {:?}", label, start.elapsed());
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    let result = handle.join().map_err(|_| format!("{} failed", label));
+    let duration = start.elapsed();
+    eprintln!("\r{} time: {:?}", label, duration);
+
+    result.map(|result| (result, duration))
+}
+
+fn is_std_stream(path: &str) -> bool {
+    path == STD_STREAM
+}
+
+fn display_name(path: &str) -> &str {
+    if is_std_stream(path) { "<stdin>" } else { path }
+}
+
+fn open_input(path: &str) -> Result<Box<dyn Read + Send>, std::io::Error> {
+    if is_std_stream(path) {
+        Ok(Box::new(std::io::stdin()))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+fn create_output(path: &str) -> Result<Box<dyn Write + Send>, std::io::Error> {
+    if is_std_stream(path) {
+        Ok(Box::new(BufWriter::new(std::io::stdout())))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+// Output files are written under a temporary name in the same directory and renamed only when write
+// succeeds, so failed encoding or decoding leaves neither partial output nor a damaged file of the same name
+fn write_output_on_success<F>(path: &str, write: F) -> Result<u64, Error>
+where F: FnOnce(&mut dyn Write) -> Result<u64, Error> {
+    if is_std_stream(path) {
+        let mut writer = BufWriter::new(std::io::stdout());
+        let count = write(&mut writer)?;
+        writer.flush()?;
+        return Ok(count);
+    }
+
+    let (temp_path, file) = create_temp_file_next_to(Path::new(path))?;
+    let mut writer = BufWriter::new(file);
+    let result = write(&mut writer).and_then(|count| {
+        writer.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+        std::fs::rename(&temp_path, path)?;
+        Ok(count)
+    });
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn create_temp_file_next_to(path: &Path) -> Result<(PathBuf, File), std::io::Error> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut attempt = 0;
+    loop {
+        let temp_path = path.with_file_name(format!(".{}.{}.{}.tmp", file_name, std::process::id(), attempt));
+        match OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// Counts bytes passed to the inner writer
+struct CountingWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.inner.flush()
+    }
+}
+
+fn read_all(path: &str) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    open_input(path).and_then(|mut input| input.read_to_end(&mut data))
+                    .map_err(|err| format!("Cannot read {}: {}", display_name(path), err))?;
+    Ok(data)
+}
+
+// Reads first bytes to detect the format, returns them together with the rest of the input
+fn open_and_detect(path: &str) -> Result<([u8; 4], Box<dyn Read + Send>), String> {
+    let mut input = open_input(path).map_err(|err| format!("Cannot read {}: {}", display_name(path), err))?;
+
+    let mut magic = [0u8; 4];
+    let mut magic_len = 0;
+    while magic_len < magic.len() {
+        let bytes_read = input.read(&mut magic[magic_len..]).map_err(|err| format!("Cannot read {}: {}", display_name(path), err))?;
+        if bytes_read == 0 {
+            break;
+        }
+        magic_len += bytes_read;
+    }
+
+    let restored: Box<dyn Read + Send> = Box::new(Cursor::new(magic[..magic_len].to_vec()).chain(input));
+    Ok((magic, restored))
+}
+
+// Random access input of seekable streams
+trait SeekableInput: Read + Seek + Send {}
+impl<T: Read + Seek + Send> SeekableInput for T {}
+
+// Seekable streams need random access: files are opened again, only piped input is collected first.
+// Output limit applies to the requested range or the whole stream.
+fn into_seekable(path: &str, mut input: Box<dyn Read + Send>, max_output_size: u64, range: Option<(u64, u64)>)
+    -> Result<Box<dyn SeekableInput>, Error> {
+    let mut reader: Box<dyn SeekableInput> = if is_std_stream(path) {
+        let mut buffer = SpillBuffer::new();
+        std::io::copy(&mut input, &mut buffer)?;
+        Box::new(buffer.into_reader()?)
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+
+    let total_size = Seekable::read_index_from(&mut reader)?.total_size;
+    let output_size = range.map_or(total_size, |(offset, length)| length.min(total_size.saturating_sub(offset)));
+    if output_size > max_output_size {
+        return Err(Error::LimitExceeded { size: output_size, limit: max_output_size });
+    }
+    Ok(reader)
+}
+
+fn read_magic(path: &str) -> Result<[u8; 4], String> {
+    let mut magic = [0u8; 4];
+    File::open(path).and_then(|mut file| file.read_exact(&mut magic))
+                    .map_err(|err| format!("Cannot read {}: {}", path, err))?;
+    Ok(magic)
+}
+
+fn file_size(path: &str) -> Result<u64, String> {
+    std::fs::metadata(path).map(|metadata| metadata.len()).map_err(|err| format!("Cannot read {}: {}", path, err))
+}
+
+fn ratio(original_size: u64, compressed_size: u64) -> f64 {
+    if original_size == 0 { 0.0 } else { compressed_size as f64 / original_size as f64 * 100.0 }
+}
+
+fn compress(options: &Options) -> Result<(), String> {
+    let input = options.input.clone();
+    let output = match options.output {
+        Some(ref output) => output.clone(),
+        None if is_std_stream(&input) => STD_STREAM.to_string(),
+        None => format!("{}{}", input, DEFAULT_EXTENSION),
+    };
+    let (checksum, block_size) = (options.checksum, options.block_size);
+
+    let (reader, codec_id, pipeline) = if options.auto {
+        let (reader, choice) = select_method(options)?;
+        eprintln!("Selected {} with {:?}, estimated size {} bytes", codec_name(choice.codec_id), choice.pipeline, choice.estimated_size);
+        (reader, choice.codec_id, choice.pipeline)
+    } else {
+        let reader = open_input(&input).map_err(|err| format!("Cannot read {}: {}", display_name(&input), err))?;
+        (reader, options.codec_id(), options.pipeline())
+    };
+
+    eprintln!("Compressing {} (codec: {}; pipeline: {:?})", display_name(&input), codec_name(codec_id), pipeline);
+
+    // Header records the input size when it is known in advance
+    let original_size = if is_std_stream(&input) {
+        None
+    } else {
+        File::open(&input).map_err(Error::from).and_then(|file| Container::regular_file_size(&file))
+                          .map_err(|err| format!("Cannot read {}: {}", display_name(&input), err))?
+    };
+
+    let output_path = output.clone();
+    let (result, _) = run_with_timer("Encoding", move || -> Result<(u64, u64), Error> {
+        let mut reader = CountingReader { inner: reader, count: 0 };
+        let compressed_size = write_output_on_success(&output_path, |output| {
+            let mut writer = CountingWriter { inner: output, count: 0 };
+
+            match block_size {
+                Some(block_size) => Seekable::compress_seekable_stream(&mut reader, &mut writer, block_size, codec_id, &pipeline, checksum)?,
+                None => {
+                    Container::encode_stream_with_size(&mut reader, &mut writer, codec_id, &pipeline, checksum, original_size)?;
+                }
+            }
+            writer.flush()?;
+
+            Ok(writer.count)
+        })?;
+
+        Ok((reader.count, compressed_size))
+    })?;
+    let (original_size, compressed_size) = result.map_err(|err| format!("Compression failed: {}", err))?;
+
+    eprintln!("{} -> {}: {} -> {} bytes ({:.2}%)", display_name(&options.input), output_name(&output),
+              original_size, compressed_size, ratio(original_size, compressed_size));
+    Ok(())
+}
+
+// Codec and pipeline not fixed by options are chosen from samples of the input, piped input is collected first.
+// Choice is stored in the header like any other, so decoding needs nothing extra.
+fn select_method(options: &Options) -> Result<(Box<dyn Read + Send>, MethodSelection::MethodChoice), String> {
+    let codecs = match options.codec_id {
+        Some(codec_id) => vec![codec_id],
+        None => registered_codecs().iter().map(|codec| codec.id()).collect(),
+    };
+    let pipelines = match options.pipeline {
+        Some(ref pipeline) => vec![pipeline.clone()],
+        None => preset_pipelines(),
+    };
+
+    let input = &options.input;
+    eprintln!("Sampling {} for {} candidate methods", display_name(input), codecs.len() * pipelines.len());
+
+    let choice = if is_std_stream(input) {
+        let mut buffer = SpillBuffer::new();
+        let mut reader = std::io::copy(&mut std::io::stdin(), &mut buffer).and_then(|_| buffer.into_reader())
+                                                                            .map_err(|err| format!("Cannot read {}: {}", display_name(input), err))?;
+        let choice = MethodSelection::choose_method(&mut reader, &codecs, &pipelines);
+        choice.map(|choice| (Box::new(reader) as Box<dyn Read + Send>, choice))
+    } else {
+        let mut reader = File::open(input).map_err(|err| format!("Cannot read {}: {}", input, err))?;
+        let choice = MethodSelection::choose_method(&mut reader, &codecs, &pipelines);
+        choice.map(|choice| (Box::new(BufReader::new(reader)) as Box<dyn Read + Send>, choice))
+    };
+    choice.map_err(|err| format!("Method selection failed: {}", err))
+}
+
+// Counts bytes taken from the inner reader
+struct CountingReader<R: Read> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        let bytes_read = self.inner.read(buf)?;
+        self.count += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+fn output_name(path: &str) -> &str {
+    if is_std_stream(path) { "<stdout>" } else { path }
+}
+
+fn default_decompressed_path(input: &str) -> String {
+    if is_std_stream(input) {
+        return STD_STREAM.to_string();
+    }
+
+    match input.strip_suffix(DEFAULT_EXTENSION) {
+        Some(stripped) if !stripped.is_empty() => stripped.to_string(),
+        _ => format!("{}.out", input),
+    }
+}
+
+fn decompress(options: &Options) -> Result<(), String> {
+    let input = options.input.clone();
+    let (magic, reader) = open_and_detect(&input)?;
+    if options.range.is_some() && magic != SEEKABLE_MAGIC {
+        return Err(format!("{} is not seekable, ranges need a stream compressed with --block-size", display_name(&input)));
+    }
+
+    if magic == ARCHIVE_MAGIC {
+        if is_std_stream(&input) {
+            return Err("Archives cannot be extracted from stdin".to_string());
+        }
+
+        let output_dir = options.output.clone().unwrap_or_else(|| ".".to_string());
+        let entries = Archive::extract_archive(&input, &output_dir, options.max_output_size).map_err(|err| format!("Extraction failed: {}", err))?;
+        eprintln!("Extracted {} entries into {}", entries.len(), output_dir);
+        return Ok(());
+    }
+
+    if magic != MAGIC && magic != SEEKABLE_MAGIC {
+        return Err(format!("{} is not a compressed file", display_name(&input)));
+    }
+
+    let output = options.output.clone().unwrap_or_else(|| default_decompressed_path(&input));
+    eprintln!("Decompressing {}", display_name(&input));
+
+    let output_path = output.clone();
+    let (max_output_size, range) = (options.max_output_size, options.range);
+    let (result, _) = run_with_timer("Decoding", move || -> Result<u64, Error> {
+        write_output_on_success(&output_path, |output| {
+            let mut writer = CountingWriter { inner: output, count: 0 };
+
+            if let Some((offset, length)) = range {
+                writer.write_all(&Seekable::read_range_from(&mut into_seekable(&input, reader, max_output_size, range)?, offset, length)?)?;
+            } else if magic == SEEKABLE_MAGIC {
+                Seekable::decompress_seekable_stream(&mut into_seekable(&input, reader, max_output_size, None)?, &mut writer)?;
+            } else {
+                std::io::copy(&mut Container::open_decoder_with_limit(reader, max_output_size)?, &mut writer)?;
+            }
+            writer.flush()?;
+
+            Ok(writer.count)
+        })
+    })?;
+    let restored_size = result.map_err(|err| format!("Decompression failed: {}", err))?;
+
+    eprintln!("{} -> {}: {} bytes", display_name(&options.input), output_name(&output), restored_size);
+    Ok(())
+}
+
+fn test(options: &Options) -> Result<(), String> {
+    let input = &options.input;
+    let (magic, reader) = open_and_detect(input)?;
+    let name = display_name(input);
+
+    if magic == MAGIC {
+        let mut decoder = Container::open_decoder_with_limit(reader, options.max_output_size).map_err(|err| format!("{}: {}", name, err))?;
+        let restored_size = std::io::copy(&mut decoder, &mut std::io::sink()).map_err(|err| format!("{}: {}", name, err))?;
+        println!("{}: OK ({} bytes)", name, restored_size);
+    } else if magic == SEEKABLE_MAGIC {
+        let mut reader = into_seekable(input, reader, options.max_output_size, None).map_err(|err| format!("{}: {}", name, err))?;
+        let mut writer = CountingWriter { inner: std::io::sink(), count: 0 };
+        Seekable::decompress_seekable_stream(&mut reader, &mut writer).map_err(|err| format!("{}: {}", name, err))?;
+        println!("{}: OK ({} bytes)", name, writer.count);
+    } else if magic == ARCHIVE_MAGIC {
+        return Err(format!("{}: testing archives is not supported, use list", name));
+    } else {
+        let mut data = Vec::new();
+        { reader }.read_to_end(&mut data).map_err(|err| format!("Cannot read {}: {}", name, err))?;
+
+        let m = Benchmark::measure(name, &data, options.codec_id(), &options.pipeline(), options.checksum)?;
+        if !m.verified {
+            return Err(format!("{}: decompressed data differs from the original", name));
+        }
+        println!("{}: round-trip OK, {} -> {} bytes ({:.2}%), encode {:?}, decode {:?}",
+                 name, m.original_size, m.compressed_size, m.ratio(), m.encode_time, m.decode_time);
+    }
+
+    Ok(())
+}
+
+fn info(options: &Options) -> Result<(), String> {
+    let input = &options.input;
+    let magic = read_magic(input)?;
+    let compressed_size = file_size(input)?;
+
+    if magic == MAGIC {
+        let header = Container::read_header(input).map_err(|err| format!("Cannot read header: {}", err))?;
+        let checksum = ChecksumSettings::from_flags(header.flags);
+
+        println!("Format:          compressed stream");
+        println!("Codec:           {}", codec_name(header.codec_id));
+        println!("Pipeline:        {:?}", header.pipeline);
+        println!("Checksum:        {:?}{}", checksum.kind, if checksum.per_block { " (with block checksums)" } else { "" });
+        if header.original_size == UNKNOWN_SIZE {
+            // Size of streams encoded on the fly follows their payload
+            println!("Original size:   unknown until decoded");
+            println!("Compressed size: {} bytes", compressed_size);
+        } else {
+            println!("Original size:   {} bytes", header.original_size);
+            println!("Compressed size: {} bytes ({:.2}%)", compressed_size, ratio(header.original_size, compressed_size));
+        }
+    } else if magic == SEEKABLE_MAGIC {
+        let index = Seekable::read_index(input).map_err(|err| format!("Cannot read index: {}", err))?;
+
+        println!("Format:          seekable stream");
+        println!("Block size:      {} bytes", index.block_size);
+        println!("Frames:          {}", index.frames.len());
+        if let Some(frame) = index.frames.first() {
+            let mut file = File::open(input).map_err(|err| err.to_string())?;
+            std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(frame.compressed_offset)).map_err(|err| err.to_string())?;
+            let header = Container::ContainerHeader::read_from(&mut file).map_err(|err| format!("Cannot read frame header: {}", err))?;
+
+            println!("Codec:           {}", codec_name(header.codec_id));
+            println!("Pipeline:        {:?}", header.pipeline);
+        }
+        println!("Original size:   {} bytes", index.total_size);
+        println!("Compressed size: {} bytes ({:.2}%)", compressed_size, ratio(index.total_size, compressed_size));
+    } else if magic == ARCHIVE_MAGIC {
+        let entries = Archive::list_archive(input).map_err(|err| format!("Cannot read archive: {}", err))?;
+        let original_size: u64 = entries.iter().map(|entry| entry.size).sum();
+
+        println!("Format:          archive");
+        println!("Entries:         {}", entries.len());
+        println!("Original size:   {} bytes", original_size);
+        println!("Compressed size: {} bytes ({:.2}%)", compressed_size, ratio(original_size, compressed_size));
+    } else {
+        return Err(format!("{} is not a compressed file", input));
+    }
+
+    Ok(())
+}
+
+fn list(options: &Options) -> Result<(), String> {
+    let entries = Archive::list_archive(&options.input).map_err(|err| format!("Cannot read archive: {}", err))?;
+
+    println!("{:>12} {:>12}  {:>6}  Path", "Size", "Compressed", "Mode");
+    for entry in entries.iter() {
+        let path = if entry.is_dir { format!("{}/", entry.path) } else { entry.path.clone() };
+        println!("{:>12} {:>12}  {:>6o}  {}", entry.size, entry.compressed_size, entry.mode, path);
+    }
+
+    Ok(())
+}
+
+fn archive_settings(options: &Options) -> ArchiveSettings {
+    ArchiveSettings {
+        codec_id: options.codec_id(),
+        pipeline: options.pipeline(),
+        checksum: options.checksum,
+        solid: options.solid,
+    }
+}
+
+// Archives are written and read by path, entries are added from files and directories only
+fn check_archive_inputs(inputs: &[String]) -> Result<Vec<&str>, String> {
+    if inputs.iter().any(|input| is_std_stream(input)) {
+        return Err("Archives cannot be built from stdin".to_string());
+    }
+    Ok(inputs.iter().map(String::as_str).collect())
+}
+
+fn print_archive_summary(archive_path: &str, message: &str) -> Result<(), String> {
+    let entries = Archive::list_archive(archive_path).map_err(|err| format!("Cannot read archive: {}", err))?;
+    let original_size: u64 = entries.iter().map(|entry| entry.size).sum();
+    let compressed_size = file_size(archive_path)?;
+
+    eprintln!("{} {}: {} entries, {} -> {} bytes ({:.2}%)", message, archive_path, entries.len(),
+              original_size, compressed_size, ratio(original_size, compressed_size));
+    Ok(())
+}
+
+fn archive(options: &Options) -> Result<(), String> {
+    let inputs = check_archive_inputs(&options.inputs)?;
+    let output = match options.output {
+        Some(ref output) => output.clone(),
+        None => {
+            let name = Archive::input_name(&options.input).map_err(|err| format!("Cannot archive {}: {}", options.input, err))?;
+            format!("{}{}", name, ARCHIVE_EXTENSION)
+        }
+    };
+    if is_std_stream(&output) {
+        return Err("Archives cannot be written to stdout".to_string());
+    }
+
+    Archive::create_archive(&output, &inputs, &archive_settings(options)).map_err(|err| format!("Archiving failed: {}", err))?;
+    print_archive_summary(&output, "Created")
+}
+
+// Non-solid archives get new entries appended, solid ones are recompressed with the given codec and transforms
+fn add(options: &Options) -> Result<(), String> {
+    let (archive_path, inputs) = options.inputs.split_first().unwrap();
+    if inputs.is_empty() {
+        return Err("Missing paths to add".to_string());
+    }
+    let inputs = check_archive_inputs(inputs)?;
+
+    if is_std_stream(archive_path) || read_magic(archive_path)? != ARCHIVE_MAGIC {
+        return Err(format!("{} is not an archive", display_name(archive_path)));
+    }
+
+    Archive::add_to_archive(archive_path, &inputs, &archive_settings(options)).map_err(|err| format!("Adding failed: {}", err))?;
+    print_archive_summary(archive_path, "Updated")
+}
+
+fn write_report(path: &str, write: impl FnOnce(&mut dyn Write) -> std::io::Result<()>) -> Result<(), String> {
+    create_output(path).and_then(|mut writer| { write(&mut writer)?; writer.flush() })
+                       .map_err(|err| format!("Cannot write {}: {}", output_name(path), err))?;
+    eprintln!("Report written to {}", output_name(path));
+    Ok(())
+}
+
+// Runs every requested codec with every requested pipeline (all presets by default) in memory on every input
+fn bench(options: &Options) -> Result<(), String> {
+    let codecs = match options.codec_id {
+        Some(codec_id) => vec![codec_id],
+        None => registered_codecs().iter().map(|codec| codec.id()).collect(),
+    };
+    let pipelines = match options.pipeline {
+        Some(ref pipeline) => vec![pipeline.clone()],
+        None => preset_pipelines(),
+    };
+
+    let mut measurements = Vec::new();
+    for input in options.inputs.iter() {
+        let data = read_all(input)?;
+
+        println!("{}: {} bytes", display_name(input), data.len());
+        println!("{:<8} {:<40} {:>12} {:>9} {:>9} {:>11} {:>11} {:>12} {:>8}",
+                 "Codec", "Pipeline", "Size", "Ratio", "Bits/B", "Enc MB/s", "Dec MB/s", "Memory", "Verified");
+
+        for &codec_id in codecs.iter() {
+            for pipeline in pipelines.iter() {
+                let m = Benchmark::measure(display_name(input), &data, codec_id, pipeline, options.checksum)?;
+                println!("{:<8} {:<40} {:>12} {:>8.2}% {:>9.3} {:>11.2} {:>11.2} {:>12} {:>8}", m.codec, format!("{:?}", m.pipeline),
+                         m.compressed_size, m.ratio(), m.bits_per_byte(), m.encode_throughput(), m.decode_throughput(),
+                         m.peak_memory, if m.verified { "yes" } else { "NO" });
+                measurements.push(m);
+            }
+        }
+        println!();
+    }
+
+    if let Some(ref path) = options.csv_report {
+        write_report(path, |writer| Benchmark::write_csv(&measurements, writer))?;
+    }
+    if let Some(ref path) = options.markdown_report {
+        write_report(path, |writer| Benchmark::write_markdown(&measurements, writer))?;
+    }
+
+    let failed = measurements.iter().filter(|m| !m.verified).count();
+    if failed > 0 {
+        return Err(format!("{} of {} round-trips did not restore the input", failed, measurements.len()));
+    }
+    Ok(())
+}
+
+// Returns process exit code: 0 on success, 1 on failure, 2 on invalid arguments
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            let codec_names: Vec<&str> = registered_codecs().iter().map(|codec| codec.name()).collect();
+            println!("{}\n\nCodecs: {}", USAGE, codec_names.join(", "));
+            return 0;
+        }
+        Err(message) => {
+            eprintln!("{}\nRun with --help for usage", message);
+            return 2;
+        }
+    };
+
+    let result = match options.command {
+        Command::Compress => compress(&options),
+        Command::Decompress => decompress(&options),
+        Command::Test => test(&options),
+        Command::Info => info(&options),
+        Command::List => list(&options),
+        Command::Archive => archive(&options),
+        Command::Add => add(&options),
+        Command::Bench => bench(&options),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(message) => {
+            eprintln!("{}", message);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_is_replaced_only_on_success() {
+        let dir = std::env::temp_dir().join(format!("lab5_cli_output_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("restored.txt");
+        let path_str = path.to_str().unwrap();
+        std::fs::write(&path, b"existing").unwrap();
+
+        let result = write_output_on_success(path_str, |output| {
+            output.write_all(b"partial")?;
+            Err(Error::corrupt(7, "damaged"))
+        });
+        assert!(matches!(result, Err(Error::CorruptData { offset: 7, .. })));
+        assert_eq!(std::fs::read(&path).unwrap(), b"existing");
+
+        let count = write_output_on_success(path_str, |output| {
+            output.write_all(b"restored")?;
+            Ok(8)
+        }).unwrap();
+        assert_eq!(count, 8);
+        assert_eq!(std::fs::read(&path).unwrap(), b"restored");
+
+        // Temporary files are gone in both cases
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
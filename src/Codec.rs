@@ -0,0 +1,65 @@
+use std::io::{Read, Write};
+
+use crate::Checksum::ChecksumSettings;
+use crate::Error::Result;
+use crate::TransformationMethods::TransformStage;
+
+// Settings shared by all codecs, codec specific ones are fields of the codec itself
+#[derive(Clone, Copy, Debug)]
+pub struct CodecParameters<'a> {
+    pub pipeline: &'a [TransformStage],
+    pub checksum: ChecksumSettings,
+    pub original_size: Option<u64>,     // Input size if known in advance, lets streaming codecs record it in the header
+}
+
+// Compressor writing complete container streams (header, data and checksum trailer), the id in
+// the header is what selects the codec when decoding
+pub trait Codec: Sync {
+    fn name(&self) -> &'static str;
+    fn id(&self) -> u8;
+
+    // Case-insensitive name, codecs can accept short forms as well
+    fn matches_name(&self, name: &str) -> bool {
+        name.eq_ignore_ascii_case(self.name())
+    }
+
+    // Compresses everything from input, output receives a complete stream
+    fn encode(&self, input: &mut dyn Read, output: &mut dyn Write, parameters: &CodecParameters) -> Result<()>;
+
+    // Input starts with the container header, streams declaring more than max_output_size bytes are rejected
+    fn open_decoder<'a>(&self, input: Box<dyn Read + 'a>, max_output_size: u64) -> Result<Box<dyn Read + 'a>>;
+}
+
+// Every codec compiled into this build, new codecs are plugged in here
+static CODECS: &[&dyn Codec] = &[
+    #[cfg(feature = "huffman")]
+    &crate::Huffman::HuffmanCodec,
+    #[cfg(feature = "lzw")]
+    &crate::LZWCoderEnhanced::LZWCodec { clear_dict_on_overfill: true },
+];
+
+pub fn registered_codecs() -> &'static [&'static dyn Codec] {
+    CODECS
+}
+
+pub fn find_codec(codec_id: u8) -> Option<&'static dyn Codec> {
+    CODECS.iter().copied().find(|codec| codec.id() == codec_id)
+}
+
+pub fn find_codec_by_name(name: &str) -> Option<&'static dyn Codec> {
+    CODECS.iter().copied().find(|codec| codec.matches_name(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registered_codecs_have_unique_ids_and_names() {
+        for (position, codec) in registered_codecs().iter().enumerate() {
+            assert_eq!(find_codec(codec.id()).map(|found| found.name()), Some(codec.name()));
+            assert_eq!(find_codec_by_name(&codec.name().to_uppercase()).map(|found| found.id()), Some(codec.id()));
+            assert!(registered_codecs()[position + 1..].iter().all(|other| other.id() != codec.id() && other.name() != codec.name()));
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
+
+use crate::Checksum::ChecksumSettings;
+use crate::Codec::{find_codec, CodecParameters};
+use crate::Error::{Error, Result};
+use crate::TransformationMethods::TransformStage;
+
+pub const MAGIC: [u8; 4] = *b"BSLC";
+pub const FORMAT_VERSION: u8 = 1;
+
+pub const CODEC_HUFFMAN: u8 = 1;
+pub const CODEC_LZW: u8 = 2;
+
+// Decoders refuse streams declaring more output than this (decompression bomb protection, 4GB)
+pub const DEFAULT_MAX_OUTPUT_SIZE: u64 = 4 << 30;
+
+// Original size in the header of streams encoded without knowing their input size in advance (LZW
+// encoding a pipe), their codec stores the size after the payload instead
+pub const UNKNOWN_SIZE: u64 = u64::MAX;
+
+// Stage count is stored in a single byte
+pub const MAX_PIPELINE_STAGES: usize = u8::MAX as usize;
+
+// Size of the header part before the transform pipeline
+const FIXED_HEADER_SIZE: usize = 16;
+const CODEC_ID_OFFSET: u64 = 5;
+
+// Header written in front of every compressed stream:
+// magic (4) | version (1) | codec id (1) | flags (1) | original size (u64) | stage count (1) | stages (2 each)
+// Original size is UNKNOWN_SIZE if the stream was encoded before its input size was known
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContainerHeader {
+    pub codec_id: u8,
+    pub flags: u8,
+    pub original_size: u64,
+    pub pipeline: Vec<TransformStage>,
+}
+
+impl ContainerHeader {
+    pub fn new(codec_id: u8, original_size: u64, pipeline: &[TransformStage]) -> Self {
+        ContainerHeader {
+            codec_id,
+            flags: 0,
+            original_size,
+            pipeline: pipeline.to_vec(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        FIXED_HEADER_SIZE + 2 * self.pipeline.len()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.size());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.push(self.codec_id);
+        bytes.push(self.flags);
+        bytes.extend_from_slice(&self.original_size.to_le_bytes());
+        bytes.push(self.pipeline.len() as u8);
+
+        for stage in self.pipeline.iter() {
+            bytes.extend_from_slice(&stage.to_bytes());
+        }
+
+        bytes
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        Ok(writer.write_all(&self.to_bytes())?)
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut fixed = [0u8; FIXED_HEADER_SIZE];
+        reader.read_exact(&mut fixed)?;
+
+        if fixed[0..4] != MAGIC {
+            return Err(Error::corrupt(0, "Not a compressed stream: invalid magic bytes"));
+        }
+        if fixed[4] != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion { format: "container format", version: fixed[4] });
+        }
+
+        let codec_id = fixed[5];
+        let flags = fixed[6];
+        let original_size = u64::from_le_bytes(fixed[7..15].try_into().unwrap());
+
+        let mut stage_bytes = vec![0u8; 2 * fixed[15] as usize];
+        reader.read_exact(&mut stage_bytes)?;
+
+        let pipeline = stage_bytes.chunks_exact(2).enumerate().map(|(stage_id, stage)| {
+            TransformStage::from_bytes([stage[0], stage[1]]).ok_or_else(|| {
+                Error::corrupt((FIXED_HEADER_SIZE + 2 * stage_id) as u64, format!("Unknown transform stage in header: {:?}", stage))
+            })
+        }).collect::<Result<Vec<_>>>()?;
+
+        Ok(ContainerHeader { codec_id, flags, original_size, pipeline })
+    }
+
+    pub fn check_output_limit(&self, max_output_size: u64) -> Result<()> {
+        if self.original_size > max_output_size {
+            return Err(Error::LimitExceeded { size: self.original_size, limit: max_output_size });
+        }
+        Ok(())
+    }
+}
+
+pub fn read_header(input_path: &str) -> Result<ContainerHeader> {
+    ContainerHeader::read_from(&mut File::open(input_path)?)
+}
+
+// Compresses everything from input into output, returns the output when the stream is complete
+pub fn encode_stream<R: Read, W: Write>(input: &mut R, output: W, codec_id: u8, pipeline: &[TransformStage],
+                                        checksum: ChecksumSettings) -> Result<W> {
+    encode_stream_with_size(input, output, codec_id, pipeline, checksum, None)
+}
+
+// Input size known in advance is recorded in the header by every codec, input has to hold exactly that many bytes
+pub fn encode_stream_with_size<R: Read, W: Write>(input: &mut R, mut output: W, codec_id: u8, pipeline: &[TransformStage],
+                                                  checksum: ChecksumSettings, original_size: Option<u64>) -> Result<W> {
+    if pipeline.len() > MAX_PIPELINE_STAGES {
+        return Err(Error::invalid_parameter(format!("Pipeline of {} stages is longer than the limit of {}", pipeline.len(), MAX_PIPELINE_STAGES)));
+    }
+    let codec = find_codec(codec_id).ok_or_else(|| unsupported_codec(codec_id))?;
+    codec.encode(input, &mut output, &CodecParameters { pipeline, checksum, original_size })?;
+    Ok(output)
+}
+
+// In-memory counterparts of encode_stream and open_decoder
+pub fn compress(data: &[u8], codec_id: u8, pipeline: &[TransformStage], checksum: ChecksumSettings) -> Result<Vec<u8>> {
+    encode_stream_with_size(&mut &data[..], Vec::new(), codec_id, pipeline, checksum, Some(data.len() as u64))
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut restored = Vec::new();
+    open_decoder(data)?.read_to_end(&mut restored)?;
+    Ok(restored)
+}
+
+// Streaming counterpart of decode_file: the header is read to pick the codec and then handed back to it
+pub fn open_decoder<'a, R: Read + 'a>(input: R) -> Result<Box<dyn Read + 'a>> {
+    open_decoder_with_limit(input, DEFAULT_MAX_OUTPUT_SIZE)
+}
+
+pub fn open_decoder_with_limit<'a, R: Read + 'a>(mut input: R, max_output_size: u64) -> Result<Box<dyn Read + 'a>> {
+    let header = ContainerHeader::read_from(&mut input)?;
+    let codec = find_codec(header.codec_id).ok_or_else(|| unknown_codec(header.codec_id))?;
+
+    codec.open_decoder(Box::new(Cursor::new(header.to_bytes()).chain(input)), max_output_size)
+}
+
+// Known codecs left out of the build are reported apart from unknown ids
+fn unsupported_codec(codec_id: u8) -> Error {
+    match codec_id {
+        CODEC_HUFFMAN | CODEC_LZW => Error::invalid_parameter(format!("Codec {} is not enabled in this build", codec_id)),
+        _ => Error::invalid_parameter(format!("Unknown codec id: {}", codec_id)),
+    }
+}
+
+// Codec id read from a header
+fn unknown_codec(codec_id: u8) -> Error {
+    match codec_id {
+        CODEC_HUFFMAN | CODEC_LZW => unsupported_codec(codec_id),
+        _ => Error::corrupt(CODEC_ID_OFFSET, format!("Unknown codec id: {}", codec_id)),
+    }
+}
+
+pub fn encode_file(input_path: &str, output_path: &str, codec_id: u8, pipeline: &[TransformStage], checksum: ChecksumSettings) -> Result<()> {
+    let input_file = File::open(input_path)?;
+    let mut writer = encode_stream_with_size(&mut BufReader::new(&input_file), BufWriter::new(File::create(output_path)?),
+                                             codec_id, pipeline, checksum, regular_file_size(&input_file)?)?;
+    Ok(writer.flush()?)
+}
+
+// Sizes of pipes and devices are not known in advance
+pub fn regular_file_size(file: &File) -> Result<Option<u64>> {
+    let metadata = file.metadata()?;
+    Ok(if metadata.is_file() { Some(metadata.len()) } else { None })
+}
+
+// Single decode entry point: codec and transforms are detected from the header
+pub fn decode_file(input_path: &str, output_path: &str) -> Result<()> {
+    decode_file_with_limit(input_path, output_path, DEFAULT_MAX_OUTPUT_SIZE)
+}
+
+pub fn decode_file_with_limit(input_path: &str, output_path: &str, max_output_size: u64) -> Result<()> {
+    let mut decoder = open_decoder_with_limit(BufReader::new(File::open(input_path)?), max_output_size)?;
+    let mut writer = BufWriter::new(File::create(output_path)?);
+
+    std::io::copy(&mut decoder, &mut writer)?;
+    Ok(writer.flush()?)
+}
+
+#[cfg(all(test, any(feature = "huffman", feature = "lzw")))]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use crate::Checksum::ChecksumKind;
+    use crate::Codec::registered_codecs;
+    use crate::TransformationMethods::{transform_pipeline, TRANSFORM_BLOCK_SIZE, TRANSFORM_PRESET_COUNT};
+
+
+    fn decode(compressed: &[u8], max_output_size: u64) -> Result<Vec<u8>> {
+        let mut restored = Vec::new();
+        open_decoder_with_limit(compressed, max_output_size)?.read_to_end(&mut restored)?;
+        Ok(restored)
+    }
+
+    fn sample_streams() -> Vec<(Vec<u8>, Vec<u8>)> {
+        let data: Vec<u8> = b"mississippi river, mississippi state ".iter().cycle().take(3000).copied().collect();
+        let checksum = ChecksumSettings::new(ChecksumKind::CRC32, true);
+
+        codec_ids().into_iter().flat_map(|codec_id| [0, 2].map(|transform_id| (codec_id, transform_id)))
+            .map(|(codec_id, transform_id)| {
+                let pipeline = transform_pipeline(transform_id).unwrap();
+                (data.clone(), encode_stream(&mut data.as_slice(), Vec::new(), codec_id, &pipeline, checksum).unwrap())
+            }).collect()
+    }
+
+    #[test]
+    fn declared_size_over_limit_is_refused() {
+        for (data, compressed) in sample_streams() {
+            let limit = data.len() as u64 - 1;
+            assert!(matches!(decode(&compressed, limit), Err(Error::LimitExceeded { limit: l, .. }) if l == limit));
+            assert_eq!(decode(&compressed, data.len() as u64).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn truncated_streams_are_corrupt() {
+        for (_, compressed) in sample_streams() {
+            for length in (FIXED_HEADER_SIZE + 4..compressed.len()).step_by(7) {
+                let result = decode(&compressed[..length], DEFAULT_MAX_OUTPUT_SIZE);
+                assert!(matches!(result, Err(Error::CorruptData { .. })), "length {}: {:?}", length, result);
+            }
+        }
+    }
+
+    #[test]
+    fn damaged_streams_never_decode_to_wrong_data() {
+        for (data, compressed) in sample_streams() {
+            for position in FIXED_HEADER_SIZE..compressed.len() {
+                let mut damaged = compressed.clone();
+                damaged[position] ^= 0x5a;
+                // Flipped padding bits are harmless, any other damage has to be noticed by a checksum at the latest
+                if let Ok(restored) = decode(&damaged, data.len() as u64) {
+                    assert_eq!(restored, data, "position {}", position);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "lzw")]
+    #[test]
+    fn damaged_payload_reports_block_checksum_mismatch() {
+        use crate::Checksum::CHECKSUM_BLOCK_SIZE;
+
+        // Byte 0xFF occurs only once in the second checksum block, so it is coded as a single literal
+        let mut data: Vec<u8> = (0..3 * CHECKSUM_BLOCK_SIZE).map(|i| (i % 200) as u8).collect();
+        let damaged_pos = CHECKSUM_BLOCK_SIZE + 1000;
+        data[damaged_pos] = 0xFF;
+
+        let checksum = ChecksumSettings::new(ChecksumKind::CRC32, true);
+        let mut compressed = encode_stream(&mut data.as_slice(), Vec::new(), CODEC_LZW, &[], checksum).unwrap();
+
+        // Codes are two bytes each, they start after the header and three LZW parameter bytes and are
+        // followed by the end code and the original size (u64)
+        let codes_start = ContainerHeader::read_from(&mut compressed.as_slice()).unwrap().size() + 3;
+        let codes_end = compressed.len() - checksum.trailer_size(data.len() as u64) as usize - 10;
+        let literal_pos = (codes_start..codes_end).step_by(2).find(|&pos| compressed[pos..pos + 2] == [0xFF, 0x00]).unwrap();
+        compressed[literal_pos] = 0xFE;
+
+        let expected_range = format!("block 1 (bytes {}..{})", CHECKSUM_BLOCK_SIZE, 2 * CHECKSUM_BLOCK_SIZE);
+        match decode(&compressed, DEFAULT_MAX_OUTPUT_SIZE) {
+            Err(Error::ChecksumMismatch(message)) => assert!(message.contains(&expected_range), "{}", message),
+            result => panic!("Expected checksum mismatch, got {:?}", result.map(|restored| restored.len())),
+        }
+    }
+
+    #[test]
+    fn pipelines_over_stage_limit_are_refused() {
+        let data = b"abracadabra";
+        let checksum = ChecksumSettings::new(ChecksumKind::CRC32, false);
+
+        for codec_id in codec_ids() {
+            let pipeline = vec![TransformStage::MTF; MAX_PIPELINE_STAGES];
+            let compressed = compress(data, codec_id, &pipeline, checksum).unwrap();
+            assert_eq!(ContainerHeader::read_from(&mut compressed.as_slice()).unwrap().pipeline, pipeline);
+            assert_eq!(decompress(&compressed).unwrap(), data);
+
+            let pipeline = vec![TransformStage::MTF; MAX_PIPELINE_STAGES + 1];
+            assert!(matches!(compress(data, codec_id, &pipeline, checksum), Err(Error::InvalidParameter(_))));
+        }
+    }
+
+    // Streams are encoded both with the size known in advance and without it
+    fn round_trip(data: &[u8], codec_id: u8, transform_id: u8) -> Vec<u8> {
+        let pipeline = transform_pipeline(transform_id).unwrap();
+        let checksum = ChecksumSettings::new(ChecksumKind::CRC32, true);
+
+        let compressed = compress(data, codec_id, &pipeline, checksum).unwrap();
+        assert_eq!(ContainerHeader::read_from(&mut compressed.as_slice()).unwrap().original_size, data.len() as u64);
+        let restored = decode(&compressed, data.len() as u64).unwrap();
+
+        let compressed = encode_stream(&mut &data[..], Vec::new(), codec_id, &pipeline, checksum).unwrap();
+        assert_eq!(decode(&compressed, data.len() as u64).unwrap(), restored);
+        restored
+    }
+
+    // Inputs codecs and transforms handle separately: no blocks, one symbol (zero-length Huffman code)
+    // and partial last blocks
+    fn edge_cases() -> Vec<Vec<u8>> {
+        let mut cases = vec![Vec::new(), vec![0x5a], vec![b'a'; 2 * TRANSFORM_BLOCK_SIZE + 1]];
+        for size in [TRANSFORM_BLOCK_SIZE - 1, TRANSFORM_BLOCK_SIZE, TRANSFORM_BLOCK_SIZE + 1, 2 * TRANSFORM_BLOCK_SIZE] {
+            cases.push((0..size).map(|i| (i * i % 251) as u8).collect());
+        }
+        cases
+    }
+
+    #[test]
+    fn edge_cases_round_trip_with_every_codec_and_transform() {
+        for codec_id in codec_ids() {
+            for transform_id in 0..TRANSFORM_PRESET_COUNT {
+                for data in edge_cases() {
+                    assert_eq!(round_trip(&data, codec_id, transform_id), data, "codec {}, transform {}, {} bytes", codec_id, transform_id, data.len());
+                }
+            }
+        }
+    }
+
+    fn codec_ids() -> Vec<u8> {
+        registered_codecs().iter().map(|codec| codec.id()).collect()
+    }
+
+    fn input_data() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![
+            prop::collection::vec(any::<u8>(), 0..256),
+            // Small alphabet gives long runs after BWT and long LZW matches
+            prop::collection::vec(0..4u8, 0..3 * TRANSFORM_BLOCK_SIZE),
+            (any::<u8>(), 0..3 * TRANSFORM_BLOCK_SIZE).prop_map(|(symbol, size)| vec![symbol; size]),
+            (TRANSFORM_BLOCK_SIZE - 2..=TRANSFORM_BLOCK_SIZE + 2).prop_flat_map(|size| prop::collection::vec(any::<u8>(), size)),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn decode_restores_encoded_data(data in input_data(), codec_id in prop::sample::select(codec_ids()),
+                                        transform_id in 0..TRANSFORM_PRESET_COUNT) {
+            prop_assert_eq!(round_trip(&data, codec_id, transform_id), data);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+
+// Errors of encoding and decoding. Offset of corrupt data is the byte position in the stream read by
+// the failing part: compressed input for codecs and containers, transformed data for inverse transforms.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    CorruptData { offset: u64, message: String },
+    UnsupportedVersion { format: &'static str, version: u8 },
+    ChecksumMismatch(String),
+    InvalidParameter(String),
+    LimitExceeded { size: u64, limit: u64 },    // Declared output is larger than the caller allows
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub fn corrupt(offset: u64, message: impl Into<String>) -> Self {
+        Error::CorruptData { offset, message: message.into() }
+    }
+
+    pub fn invalid_parameter(message: impl Into<String>) -> Self {
+        Error::InvalidParameter(message.into())
+    }
+
+    // Input ending inside a stream means it was truncated
+    pub fn truncated_at(self, offset: u64) -> Self {
+        match self {
+            Error::Io(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => Error::corrupt(offset, "Unexpected end of stream"),
+            err => err,
+        }
+    }
+
+    // Errors found inside a block are reported relative to it, base is the position of the block in the stream
+    pub fn at_offset(self, base: u64) -> Self {
+        match self {
+            Error::CorruptData { offset, message } => Error::CorruptData { offset: base + offset, message },
+            err => err,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::CorruptData { offset, message } => write!(f, "Corrupted data at offset {}: {}", offset, message),
+            Error::UnsupportedVersion { format, version } => write!(f, "Unsupported {} version: {}", format, version),
+            Error::ChecksumMismatch(message) => write!(f, "{}", message),
+            Error::InvalidParameter(message) => write!(f, "Invalid parameter: {}", message),
+            Error::LimitExceeded { size, limit } => write!(f, "Output size {} exceeds the limit of {} bytes", size, limit),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        // Errors of this crate passed through Read and Write implementations are unwrapped back
+        if !err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            return Error::Io(err);
+        }
+
+        let kind = err.kind();
+        match err.into_inner().map(|inner| inner.downcast::<Error>()) {
+            Some(Ok(inner)) => *inner,
+            Some(Err(inner)) => Error::Io(std::io::Error::new(kind, inner)),
+            None => Error::Io(kind.into()),
+        }
+    }
+}
+
+// Read and Write implementations can only return io::Error, the original error is kept inside
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            Error::InvalidParameter(_) => std::io::Error::new(std::io::ErrorKind::InvalidInput, err),
+            Error::LimitExceeded { .. } => std::io::Error::new(std::io::ErrorKind::FileTooLarge, err),
+            _ => std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survives_io_error_round_trip() {
+        let io_error: std::io::Error = Error::corrupt(42, "Bad code").into();
+        assert_eq!(io_error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(matches!(Error::from(io_error), Error::CorruptData { offset: 42, .. }));
+
+        let io_error = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        assert!(matches!(Error::from(io_error), Error::Io(err) if err.kind() == std::io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn offsets_are_moved_by_base() {
+        assert!(matches!(Error::corrupt(3, "").at_offset(10), Error::CorruptData { offset: 13, .. }));
+        assert!(matches!(Error::invalid_parameter("").at_offset(10), Error::InvalidParameter(_)));
+    }
+}
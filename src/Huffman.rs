@@ -1,255 +1,421 @@
-use crate::BitStream::BitStream;
+use crate::BitStream::{BitReader, BitWriter};
+use crate::Codec::{Codec, CodecParameters};
+use crate::Checksum::{verify, ChecksumSettings, ChecksumTrailer, StreamChecksum};
+use crate::Container::{ContainerHeader, CODEC_HUFFMAN, DEFAULT_MAX_OUTPUT_SIZE};
+use crate::Error::{Error, Result};
+use crate::SpillBuffer::SpillBuffer;
 use crate::TransformationMethods::*;
-use std::fs::remove_file;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
 
 struct Node {
-    weight: u32,
+    weight: u64,    // Sum of up to 256 u32 frequencies
     byte_value: Option<u8>,
-    parent: Option<Box<Node>>,
     left: Option<Box<Node>>,
     right: Option<Box<Node>>,
 }
 
-pub struct HuffmanEncoder {
-    freq_t: [u32; 256],
-    root: Option<Box<Node>>,
-    input_stream: BitStream,
-    output_stream: BitStream,
-    codes: [([u8; 32], u8); 256], // (code, code_length)
-}
+type CodeTable = [([u8; 32], u8); 256]; // (code, code_length)
+
+// Root is None for empty input
+fn build_tree_and_get_codes(freq_t: &[u32; 256]) -> (Option<Box<Node>>, Box<CodeTable>) {
+    let mut queue: Vec<Box<Node>> = Vec::new();
+    for (i, &freq) in freq_t.iter().enumerate() {
+        if freq != 0 {
+            queue.push(Box::new(Node {
+                weight: freq as u64,
+                byte_value: Some(i as u8),
+                left: None,
+                right: None,
+            }));
+        }
+    }
 
-pub struct HuffmanDecoder {
-    freq_t: [u32; 256],
-    root: Option<Box<Node>>,
-    input_stream: BitStream,
-    output_stream: BitStream,
-    codes: [([u8; 32], u8); 256], // (code, code_length)
-}
+    // Build Huffman tree
+    while queue.len() > 1 {
+        queue.sort_by_key(|node| node.weight);
+        let left = queue.remove(0);
+        let right = queue.remove(0);
 
-impl HuffmanEncoder {
-    fn calc_frequences(&mut self) {
-        while let Ok(byte_arr) = self.input_stream.read_bit_sequence(8) {
-            if byte_arr.len() == 0 {
-                break;
-            }
+        let parent = Box::new(Node {
+            weight: left.weight + right.weight,
+            byte_value: None,
+            left: Some(left),
+            right: Some(right),
+        });
 
-            self.freq_t[byte_arr[0] as usize] += 1;
-        }
+        queue.push(parent);
+    }
+
+    let root = queue.pop();
+    let mut codes: Box<CodeTable> = Box::new([([0; 32], 0); 256]);
 
-        self.input_stream.rewind_read_stream().unwrap();
+    // Traverse tree to get codes
+    let mut stack: Vec<(&Node, [u8; 32], u8)> = Vec::new();
+    if let Some(ref root) = root {
+        stack.push((root, [0; 32], 0));
     }
 
-    fn build_tree_and_get_codes(&mut self) {
-        let mut queue: Vec<Box<Node>> = Vec::new();
-        for (i, &freq) in self.freq_t.iter().enumerate() {
-            if freq != 0 {
-                queue.push(Box::new(Node {
-                    weight: freq,
-                    byte_value: Some(i as u8),
-                    parent: None,
-                    left: None,
-                    right: None,
-                }));
+    while let Some((node, acc_code, code_length)) = stack.pop() {
+        if let Some(byte_value) = node.byte_value {
+            codes[byte_value as usize] = (acc_code, code_length);
+        } else {
+            if let Some(ref right) = node.right {
+                let mut r_acc_code = acc_code;
+                r_acc_code[(code_length / 8) as usize] |= 1 << (code_length % 8);
+                stack.push((right, r_acc_code, code_length + 1));
+            }
+
+            if let Some(ref left) = node.left {
+                stack.push((left, acc_code, code_length + 1));
             }
         }
+    }
 
-        // Build Huffman tree
-        queue.sort_by_key(|node| node.weight);
-        while queue.len() > 1 {
-            queue.sort_by_key(|node| node.weight);
-            let left = queue.remove(0);
-            let right = queue.remove(0);
-
-            let parent = Box::new(Node {
-                weight: left.weight + right.weight,
-                byte_value: None,
-                parent: None,
-                left: Some(left),
-                right: Some(right),
-            });
-
-            queue.push(parent);
-        }
-
-        self.root = Some(queue.remove(0));
-
-        // Traverse tree to get codes
-        let mut stack: Vec<(&Node, [u8; 32], u8)> = Vec::new();
-        stack.push((self.root.as_ref().unwrap(), [0; 32], 0));
-
-        while let Some((node, acc_code, code_length)) = stack.pop() {
-            if let Some(byte_value) = node.byte_value {
-                self.codes[byte_value as usize] = (acc_code, code_length);
-            } else {
-                if let Some(ref right) = node.right {
-                    let mut r_acc_code = acc_code.clone();
-                    r_acc_code[(code_length / 8) as usize] |= 1 << (code_length % 8);
-                    stack.push((right, r_acc_code, code_length + 1));
-                }
+    (root, codes)
+}
 
-                if let Some(ref left) = node.left {
-                    stack.push((left, acc_code, code_length + 1));
-                }
-            }
+// Huffman needs frequencies of the whole input, so transformed data is collected (in memory
+// or a spill file for large inputs) and the compressed stream is produced by finish()
+pub struct HuffmanEncoder<W: Write> {
+    output: Option<W>,  // Taken by finish()
+    pipeline: Vec<TransformStage>,
+    checksum_settings: ChecksumSettings,
+    checksum: StreamChecksum,
+    transformer: BlockTransformer,
+    freq_t: [u32; 256],
+    original_size: u64,
+    transformed: SpillBuffer,
+    finished: bool,
+}
+
+impl<W: Write> HuffmanEncoder<W> {
+    pub fn new(output: W, pipeline: &[TransformStage], checksum: ChecksumSettings) -> Self {
+        HuffmanEncoder {
+            output: Some(output),
+            pipeline: pipeline.to_vec(),
+            checksum_settings: checksum,
+            checksum: StreamChecksum::new(checksum),
+            transformer: BlockTransformer::new(pipeline),
+            freq_t: [0; 256],
+            original_size: 0,
+            transformed: SpillBuffer::new(),
+            finished: false,
         }
     }
 
-    pub fn encode(input: &str, output: &str, transform_id: u8) {
-        let file_to_endcode = if transform_id != 0 {
-            let tempfile = format!("{}.tmp", input); 
-            transform_file(input, &tempfile, transform_id);
-            tempfile
+    fn collect_transformed(&mut self, data: &[u8]) -> Result<()> {
+        for &byte in data.iter() {
+            // Frequency table stores u32 counts
+            self.freq_t[byte as usize] = self.freq_t[byte as usize].checked_add(1).ok_or_else(|| {
+                Error::invalid_parameter(format!("Huffman stream cannot hold more than {} occurrences of a byte", u32::MAX))
+            })?;
         }
-        else {
-            input.to_string()
-        };
+        Ok(self.transformed.write_all(data)?)
+    }
 
-        let mut internal_encoder = HuffmanEncoder {
-            freq_t: [0; 256],
-            root: None,
-            input_stream: BitStream::new(&file_to_endcode, true),
-            output_stream: BitStream::new(output, false),
-            codes: [([0; 32], 0); 256],
-        };
+    fn finish_stream(&mut self) -> Result<()> {
+        self.finished = true;
+
+        let last_block = self.transformer.finish()?;
+        self.collect_transformed(&last_block)?;
+
+        let (_, codes) = build_tree_and_get_codes(&self.freq_t);
+
+        let mut output_stream = BitWriter::new(self.output.as_mut().unwrap());
 
-        internal_encoder.output_stream.clear_output_file().unwrap();
+        let mut header = ContainerHeader::new(CODEC_HUFFMAN, self.original_size, &self.pipeline);
+        header.flags = self.checksum_settings.to_flags();
+        header.write_to(&mut output_stream)?;
 
-        internal_encoder.calc_frequences();
-        internal_encoder.build_tree_and_get_codes();
-        
         // Write frequency table to output
-        for (i, freq) in internal_encoder.freq_t.iter().enumerate() {
-            internal_encoder.output_stream.write_bit_sequence(&u32::to_le_bytes(*freq), 32).unwrap();
+        for freq in self.freq_t.iter() {
+            output_stream.write_bit_sequence(&freq.to_le_bytes(), 32)?;
         }
-        
+
         // Encode all bytes
-        while let Ok(byte_arr) = internal_encoder.input_stream.read_bit_sequence(8) {
-            if byte_arr.len() == 0 {
+        let mut transformed = std::mem::take(&mut self.transformed).into_reader()?;
+        let mut slice = vec![0u8; TRANSFORM_BLOCK_SIZE];
+        loop {
+            let bytes_read = transformed.read(&mut slice)?;
+            if bytes_read == 0 {
                 break;
             }
 
-            let byte = byte_arr[0];
-            let (code, code_length) = internal_encoder.codes[byte as usize];
-
-            internal_encoder.output_stream.write_bit_sequence(&code, code_length as usize).unwrap();
+            for &byte in slice[..bytes_read].iter() {
+                let (code, code_length) = &codes[byte as usize];
+                output_stream.write_bit_sequence(code, *code_length as usize)?;
+            }
         }
 
-        internal_encoder.output_stream.flush().unwrap();
+        output_stream.flush()?;
+
+        // Checksums of the original data are stored after the byte-aligned code stream
+        let checksum = std::mem::replace(&mut self.checksum, StreamChecksum::new(self.checksum_settings)).finish();
+        checksum.write_to(&mut output_stream, self.checksum_settings)?;
+
+        output_stream.into_inner()?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.finish_stream()?;
+        Ok(self.output.take().unwrap())
+    }
+}
+
+impl<W: Write> Write for HuffmanEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        self.checksum.update(buf);
+        self.original_size += buf.len() as u64;
 
-        if transform_id != 0 {
-            remove_file(file_to_endcode).unwrap();
+        let transformed = self.transformer.push(buf)?;
+        self.collect_transformed(&transformed)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+}
+
+// Encoder dropped without finish() completes the stream, errors can only be reported by finish()
+impl<W: Write> Drop for HuffmanEncoder<W> {
+    fn drop(&mut self) {
+        if !self.finished && !std::thread::panicking() {
+            let _ = self.finish_stream();
         }
     }
 }
 
-impl HuffmanDecoder {
-    fn build_tree_and_get_codes(&mut self) {
-        let mut queue: Vec<Box<Node>> = Vec::new();
-        for (i, &freq) in self.freq_t.iter().enumerate() {
-            if freq != 0 {
-                queue.push(Box::new(Node {
-                    weight: freq,
-                    byte_value: Some(i as u8),
-                    parent: None,
-                    left: None,
-                    right: None,
-                }));
-            }
+impl HuffmanEncoder<BufWriter<File>> {
+    pub fn encode(input: &str, output: &str, pipeline: &[TransformStage], checksum: ChecksumSettings) -> Result<()> {
+        let mut encoder = HuffmanEncoder::new(BufWriter::new(File::create(output)?), pipeline, checksum);
+        std::io::copy(&mut File::open(input)?, &mut encoder)?;
+        encoder.finish()?.flush()?;
+        Ok(())
+    }
+}
+
+pub struct HuffmanDecoder<R: Read> {
+    input_stream: BitReader<R>,
+    header: ContainerHeader,
+    root: Option<Box<Node>>,
+    symbols_left: u64,
+    transformer: InverseBlockTransformer,
+    checksum: StreamChecksum,
+    output: Vec<u8>,
+    output_pos: usize,
+    finished: bool,
+}
+
+impl<R: Read> HuffmanDecoder<R> {
+    pub fn new(input: R) -> Result<Self> {
+        Self::with_output_limit(input, DEFAULT_MAX_OUTPUT_SIZE)
+    }
+
+    // Streams declaring more than max_output_size bytes are rejected before decoding
+    pub fn with_output_limit(input: R, max_output_size: u64) -> Result<Self> {
+        let mut input_stream = BitReader::new(input);
+
+        let header = ContainerHeader::read_from(&mut input_stream)?;
+        if header.codec_id != CODEC_HUFFMAN {
+            return Err(Error::invalid_parameter("Stream is not Huffman encoded"));
         }
+        header.check_output_limit(max_output_size)?;
 
-        // Build Huffman tree
-        while queue.len() > 1 {
-            queue.sort_by_key(|node| node.weight);
-            let left = queue.remove(0);
-            let right = queue.remove(0);
+        // Read frequency table from input
+        let mut table_bytes = [0u8; 1024];
+        input_stream.read_exact(&mut table_bytes).map_err(|err| Error::from(err).truncated_at(header.size() as u64))?;
 
-            let parent = Box::new(Node {
-                weight: left.weight + right.weight,
-                byte_value: None,
-                parent: None,
-                left: Some(left),
-                right: Some(right),
-            });
+        let mut freq_t = [0u32; 256];
+        for (i, freq) in freq_t.iter_mut().enumerate() {
+            *freq = u32::from_le_bytes(table_bytes[i*4..i*4+4].try_into().unwrap());
+        }
 
-            queue.push(parent);
+        // Symbols with zero-length codes take no input, so their count has to be limited by the header
+        let symbols: u64 = freq_t.iter().map(|&freq| freq as u64).sum();
+        if symbols > max_transformed_size(header.original_size, &header.pipeline) {
+            return Err(Error::corrupt(header.size() as u64, format!("Frequency table holds {} symbols, more than {} bytes can be transformed to",
+                                                                    symbols, header.original_size)));
         }
 
-        self.root = Some(queue.remove(0));
+        let (root, _) = build_tree_and_get_codes(&freq_t);
+
+        Ok(HuffmanDecoder {
+            input_stream,
+            symbols_left: symbols,
+            transformer: InverseBlockTransformer::new(&header.pipeline, Some(header.original_size)),
+            checksum: StreamChecksum::new(ChecksumSettings::from_flags(header.flags)),
+            header,
+            root,
+            output: Vec::new(),
+            output_pos: 0,
+            finished: false,
+        })
+    }
 
-        // Traverse tree to get codes
-        let mut stack: Vec<(&Node, [u8; 32], u8)> = Vec::new();
-        stack.push((self.root.as_ref().unwrap(), [0; 32], 0));
+    pub fn header(&self) -> &ContainerHeader {
+        &self.header
+    }
 
-        while let Some((node, acc_code, code_length)) = stack.pop() {
-            if let Some(byte_value) = node.byte_value {
-                self.codes[byte_value as usize] = (acc_code, code_length);
-            } else {
-                if let Some(ref right) = node.right {
-                    let mut r_acc_code = acc_code;
-                    r_acc_code[(code_length / 8) as usize] |= 1 << (code_length % 8);
-                    stack.push((right, r_acc_code, code_length + 1));
+    // Decodes next portion of symbols into the output buffer
+    fn decode_chunk(&mut self) -> Result<()> {
+        let chunk_size = self.symbols_left.min(TRANSFORM_BLOCK_SIZE as u64);
+        let mut decoded: Vec<u8> = Vec::with_capacity(chunk_size as usize);
+
+        if let Some(root) = self.root.as_deref() {
+            for _ in 0..chunk_size {
+                // Single symbol alphabet has zero-length code, root is already a leaf
+                let mut current_node = root;
+                while current_node.byte_value.is_none() {
+                    let bit = self.input_stream.read_bits(1).map_err(|err| Error::from(err).truncated_at(self.input_stream.bit_position() / 8))?;
+                    current_node = if bit == 0 {
+                        current_node.left.as_ref().unwrap()
+                    } else {
+                        current_node.right.as_ref().unwrap()
+                    };
                 }
 
-                if let Some(ref left) = node.left {
-                    stack.push((left, acc_code, code_length + 1));
-                }
+                decoded.push(current_node.byte_value.unwrap());
             }
         }
+        self.symbols_left -= chunk_size;
+
+        self.output = self.transformer.push(&decoded)?;
+        self.output_pos = 0;
+        self.checksum.update(&self.output);
+
+        if self.symbols_left == 0 {
+            self.finish_stream()?;
+        }
+
+        Ok(())
     }
 
-    pub fn decode(input: &str, output: &str, transform_id: u8) {
-        let decoded_output = if transform_id != 0 {
-            format!("{}.tmp", output)
-        } else {
-            output.to_string()
-        };
+    fn finish_stream(&mut self) -> Result<()> {
+        self.finished = true;
 
-        let mut internal_decoder = HuffmanDecoder {
-            freq_t: [0; 256],
-            root: None,
-            input_stream: BitStream::new(input, true),
-            output_stream: BitStream::new(&decoded_output, false),
-            codes: [([0; 32], 0); 256],
-        };
-
-        internal_decoder.output_stream.clear_output_file().unwrap();
-        
-        // Read frequency table from input
-        let table_bytes = internal_decoder.input_stream.read_bit_sequence(8192).unwrap();
-        for (i, freq) in internal_decoder.freq_t.iter_mut().enumerate() {
-            *freq = u32::from_le_bytes(table_bytes[i*4..i*4+4].try_into().unwrap());
+        if self.transformer.restored_size() != self.header.original_size {
+            return Err(Error::corrupt(self.input_stream.bit_position() / 8, "Decoded size does not match the header"));
         }
-        
-        internal_decoder.build_tree_and_get_codes();
 
-        // Decode all bytes
-        let mut symbols_left: u32 = internal_decoder.freq_t.iter().sum();
-        let mut current_node = internal_decoder.root.as_ref().unwrap();
-        while let Ok(byte_arr) = internal_decoder.input_stream.read_bit_sequence(1) {
-            if byte_arr.len() == 0 || symbols_left == 0 {
-                break;
-            }
+        // Unused bits of the last code byte are padding, trailer follows
+        self.input_stream.align_to_byte()?;
 
-            let read_val = byte_arr[0]; 
-            current_node = if read_val == 0 {
-                current_node.left.as_ref().unwrap()
-            } else {
-                current_node.right.as_ref().unwrap()
-            };
+        let settings = ChecksumSettings::from_flags(self.header.flags);
+        let trailer_offset = self.input_stream.bit_position() / 8;
+        let expected = ChecksumTrailer::read_from(&mut self.input_stream, settings, self.header.original_size)
+                                      .map_err(|err| Error::from(err).truncated_at(trailer_offset))?;
+        let actual = std::mem::replace(&mut self.checksum, StreamChecksum::new(settings)).finish();
 
-            if let Some(byte_value) = current_node.byte_value {
-                internal_decoder.output_stream.write_bit_sequence(&[current_node.byte_value.unwrap()], 8).unwrap();
-                current_node = internal_decoder.root.as_ref().unwrap();
+        verify(&expected, &actual)
+    }
+}
 
-                symbols_left -= 1;
-            }
+impl<R: Read> Read for HuffmanDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        while self.output_pos == self.output.len() && !self.finished {
+            self.decode_chunk()?;
         }
 
-        internal_decoder.output_stream.flush().unwrap();
+        let bytes_to_copy = buf.len().min(self.output.len() - self.output_pos);
+        buf[..bytes_to_copy].copy_from_slice(&self.output[self.output_pos..self.output_pos + bytes_to_copy]);
+        self.output_pos += bytes_to_copy;
+
+        Ok(bytes_to_copy)
+    }
+}
 
-        if transform_id != 0 {
-            inverse_transform_file(&decoded_output, output, transform_id);
-            remove_file(decoded_output).unwrap();
+impl HuffmanDecoder<File> {
+    pub fn decode(input: &str, output: &str) -> Result<()> {
+        let mut decoder = HuffmanDecoder::new(File::open(input)?)?;
+        let mut writer = BufWriter::new(File::create(output)?);
+
+        std::io::copy(&mut decoder, &mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+pub struct HuffmanCodec;
+
+impl Codec for HuffmanCodec {
+    fn name(&self) -> &'static str {
+        "huffman"
+    }
+
+    fn id(&self) -> u8 {
+        CODEC_HUFFMAN
+    }
+
+    fn matches_name(&self, name: &str) -> bool {
+        name.eq_ignore_ascii_case("huffman") || name.eq_ignore_ascii_case("huff")
+    }
+
+    fn encode(&self, input: &mut dyn Read, output: &mut dyn Write, parameters: &CodecParameters) -> Result<()> {
+        let mut encoder = HuffmanEncoder::new(output, parameters.pipeline, parameters.checksum);
+        std::io::copy(input, &mut encoder)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    fn open_decoder<'a>(&self, input: Box<dyn Read + 'a>, max_output_size: u64) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(HuffmanDecoder::with_output_limit(input, max_output_size)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Checksum::ChecksumKind;
+
+    fn sample_data() -> Vec<u8> {
+        (0..2 * TRANSFORM_BLOCK_SIZE + 5000).map(|i| ((i % 251) ^ (i / 1000)) as u8).collect()
+    }
+
+    fn decode_in_single_bytes(compressed: &[u8]) -> Vec<u8> {
+        let mut decoder = HuffmanDecoder::new(compressed).unwrap();
+        let mut restored = Vec::new();
+        let mut byte = [0u8; 1];
+        while decoder.read(&mut byte).unwrap() == 1 {
+            restored.push(byte[0]);
         }
+        restored
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn adapters_handle_odd_sized_writes_and_single_byte_reads() {
+        let data = sample_data();
+        let checksum = ChecksumSettings::new(ChecksumKind::CRC32, true);
+
+        for transform_id in [0, 1, 7] {
+            let mut encoder = HuffmanEncoder::new(Vec::new(), &transform_pipeline(transform_id).unwrap(), checksum);
+            let mut remaining = data.as_slice();
+            for chunk_size in [1, 7, 4093, 65537].iter().cycle() {
+                if remaining.is_empty() {
+                    break;
+                }
+                let (chunk, rest) = remaining.split_at((*chunk_size).min(remaining.len()));
+                encoder.write_all(chunk).unwrap();
+                remaining = rest;
+            }
+            let compressed = encoder.finish().unwrap();
+
+            assert!(decode_in_single_bytes(&compressed) == data, "transform {}", transform_id);
+        }
+    }
+
+    #[test]
+    fn dropped_encoder_finishes_the_stream() {
+        let data = sample_data();
+        let mut compressed = Vec::new();
+
+        let mut encoder = HuffmanEncoder::new(&mut compressed, &transform_pipeline(1).unwrap(), ChecksumSettings::new(ChecksumKind::XXH64, false));
+        encoder.write_all(&data).unwrap();
+        drop(encoder);
+
+        assert!(decode_in_single_bytes(&compressed) == data);
+    }
+}
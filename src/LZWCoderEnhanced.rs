@@ -1,9 +1,15 @@
-const MAX_DICT_SIZE: usize = 0xFFFF;
+// Two highest codes are reserved for the clear and end symbols
+const MAX_DICT_SIZE: usize = 0xFFFE;
 const CLEAR_SYMBOL: u16 = 0xFFFF;
-use std::fs::{File, OpenOptions};
+const END_SYMBOL: u16 = 0xFFFE;
+use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Write};
 use std::collections::HashMap;
 
+use crate::Codec::{Codec, CodecParameters};
+use crate::Checksum::{verify, ChecksumSettings, ChecksumTrailer, StreamChecksum};
+use crate::Container::{regular_file_size, ContainerHeader, CODEC_LZW, DEFAULT_MAX_OUTPUT_SIZE, UNKNOWN_SIZE};
+use crate::Error::{Error, Result};
 use crate::TransformationMethods::*;
 
 struct LZWCoderEnhanced {
@@ -38,9 +44,9 @@ impl LZWCoderEnhanced {
             self.dict.push((char, idx));
             self.reverse_dict_map.insert((char, idx), self.get_last_dict_index());
             
-            return true;
+            true
         } else {
-            return false;
+            false
         }
     }
 
@@ -69,191 +75,509 @@ impl LZWCoderEnhanced {
     }
 }
 
-pub fn encode_file(input_path: &str, output_path: &str, clear_dict_on_overfill: bool, tranform_id: u8) {
-    let input_file = File::open(input_path).unwrap();
-    let mut reader = BufReader::new(input_file);
-
-    let output_file = OpenOptions::new().write(true)
-                                        .create(true)
-                                        .truncate(true)
-                                        .open(output_path).unwrap();
-    let mut writer = BufWriter::new(output_file);
-
-    // Create encoder and initialize dictionary
-    let mut internal_encoder = LZWCoderEnhanced {
-        dict: Vec::new(),
-        reverse_dict_map: HashMap::new(),
-        max_dict_size: MAX_DICT_SIZE,
-        clear_dict_on_overfill
-    };
-
-    // Store parameters for decoder into first three bytes
-    writer.write(&[ if clear_dict_on_overfill { 1 } else { 0 } ]).unwrap();
-    writer.write(&(internal_encoder.max_dict_size as u16).to_le_bytes()).unwrap();
-
-    internal_encoder.set_init_dict();
-
-    let mut I: Option<u16> = None;
-
-    if tranform_id != 0 {
-        let mut slice: Vec<u8> = Vec::with_capacity(TRANSFORM_BLOCK_SIZE);
-        slice.resize(TRANSFORM_BLOCK_SIZE, 0);
-    
-        while let Ok(_bytes_read) = reader.read(&mut slice) {   // Buffered read for transformation
-            if _bytes_read == 0 {
-                break;  // EOF
-            }
+// Codes are written as soon as they are produced. Unless the input size is known in advance, the header
+// marks the original size as unknown and the size follows the end code instead.
+pub struct LZWEncoder<W: Write> {
+    output: Option<W>,  // Taken by finish()
+    internal_encoder: LZWCoderEnhanced,
+    transformer: BlockTransformer,
+    checksum_settings: ChecksumSettings,
+    checksum: StreamChecksum,
+    original_size: u64,
+    declared_size: Option<u64>,  // Size recorded in the header
+    pending: Vec<u8>,   // Header and codes not written to the output yet
+    I: Option<u16>,
+    finished: bool,
+}
 
-            slice.truncate(_bytes_read);
+impl<W: Write> LZWEncoder<W> {
+    pub fn new(output: W, clear_dict_on_overfill: bool, pipeline: &[TransformStage], checksum: ChecksumSettings) -> Self {
+        Self::with_original_size(output, clear_dict_on_overfill, pipeline, checksum, None)
+    }
 
-            for &byte in perform_transform(&slice.to_vec(), tranform_id).iter() {
-                if let Some(idx) = internal_encoder.find_seq_in_dict((byte, I)) {
-                    I = Some(idx);
-                } else {
-                    writer.write(&I.unwrap().to_le_bytes()).unwrap();
+    // Known size goes into the header, finish() fails if the input has a different size
+    pub fn with_original_size(output: W, clear_dict_on_overfill: bool, pipeline: &[TransformStage], checksum: ChecksumSettings,
+                              original_size: Option<u64>) -> Self {
+        // Create encoder and initialize dictionary
+        let mut internal_encoder = LZWCoderEnhanced {
+            dict: Vec::new(),
+            reverse_dict_map: HashMap::new(),
+            max_dict_size: MAX_DICT_SIZE,
+            clear_dict_on_overfill
+        };
+        internal_encoder.set_init_dict();
+
+        let mut header = ContainerHeader::new(CODEC_LZW, original_size.unwrap_or(UNKNOWN_SIZE), pipeline);
+        header.flags = checksum.to_flags();
+        let mut pending = header.to_bytes();
+
+        // Store parameters for decoder into first three bytes after the header
+        pending.push(if clear_dict_on_overfill { 1 } else { 0 });
+        pending.extend_from_slice(&(MAX_DICT_SIZE as u16).to_le_bytes());
+
+        LZWEncoder {
+            output: Some(output),
+            internal_encoder,
+            transformer: BlockTransformer::new(pipeline),
+            checksum_settings: checksum,
+            checksum: StreamChecksum::new(checksum),
+            original_size: 0,
+            declared_size: original_size,
+            pending,
+            I: None,
+            finished: false,
+        }
+    }
 
-                    let pair_added = internal_encoder.add_seq_to_dict((byte, I));
+    fn encode_bytes(&mut self, data: &[u8]) {
+        for &byte in data.iter() {
+            if let Some(idx) = self.internal_encoder.find_seq_in_dict((byte, self.I)) {
+                self.I = Some(idx);
+            } else {
+                self.pending.extend_from_slice(&self.I.unwrap().to_le_bytes());
 
-                    if !pair_added && internal_encoder.clear_dict_on_overfill {
-                        internal_encoder.set_init_dict();
-                        writer.write(&CLEAR_SYMBOL.to_le_bytes()).unwrap();
-                    }
+                let pair_added = self.internal_encoder.add_seq_to_dict((byte, self.I));
 
-                    I = Some(byte as u16);  // I -> idx of byte (bytes are filled sequentially)
+                if !pair_added && self.internal_encoder.clear_dict_on_overfill {
+                    self.internal_encoder.set_init_dict();
+                    self.pending.extend_from_slice(&CLEAR_SYMBOL.to_le_bytes());
                 }
+
+                self.I = Some(byte as u16);  // I -> idx of byte (bytes are filled sequentially)
             }
         }
-    } else {
-        // Default behavior without transformation
-        for byte in reader.bytes().map(|b| b.unwrap()) {
-            if let Some(idx) = internal_encoder.find_seq_in_dict((byte, I)) {
-                I = Some(idx);
-            } else {
-                writer.write(&I.unwrap().to_le_bytes()).unwrap();
+    }
 
-                let pair_added = internal_encoder.add_seq_to_dict((byte, I));
+    fn write_pending(&mut self) -> Result<()> {
+        self.output.as_mut().unwrap().write_all(&self.pending)?;
+        self.pending.clear();
+        Ok(())
+    }
 
-                if !pair_added && internal_encoder.clear_dict_on_overfill {
-                    internal_encoder.set_init_dict();
-                    writer.write(&CLEAR_SYMBOL.to_le_bytes()).unwrap();
-                }
+    fn finish_stream(&mut self) -> Result<()> {
+        self.finished = true;
+
+        let transformed = self.transformer.finish()?;
+        self.encode_bytes(&transformed);
+
+        if let Some(I) = self.I.take() {
+            self.pending.extend_from_slice(&I.to_le_bytes());
+        }
 
-                I = Some(byte as u16);  // I -> idx of byte (bytes are filled sequentially)
+        // Size not recorded in the header follows the end code (the only code of empty input)
+        match self.declared_size {
+            Some(declared_size) if declared_size != self.original_size => {
+                return Err(Error::invalid_parameter(format!("Input of {} bytes does not match its declared size of {} bytes",
+                                                            self.original_size, declared_size)));
+            }
+            Some(_) => {}
+            None => {
+                self.pending.extend_from_slice(&END_SYMBOL.to_le_bytes());
+                self.pending.extend_from_slice(&self.original_size.to_le_bytes());
             }
         }
+        self.write_pending()?;
+
+        // Checksums of the original data are stored after the codes or the size
+        let checksum = std::mem::replace(&mut self.checksum, StreamChecksum::new(self.checksum_settings)).finish();
+        let output = self.output.as_mut().unwrap();
+        checksum.write_to(output, self.checksum_settings)?;
+
+        Ok(output.flush()?)
     }
-     
 
-    writer.write(&I.unwrap().to_le_bytes()).unwrap();
+    pub fn finish(mut self) -> Result<W> {
+        self.finish_stream()?;
+        Ok(self.output.take().unwrap())
+    }
+}
+
+impl<W: Write> Write for LZWEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        self.checksum.update(buf);
+        self.original_size += buf.len() as u64;
+
+        let transformed = self.transformer.push(buf)?;
+        self.encode_bytes(&transformed);
+        self.write_pending()?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.output.as_mut().unwrap().flush()
+    }
+}
+
+// Encoder dropped without finish() completes the stream, errors can only be reported by finish()
+impl<W: Write> Drop for LZWEncoder<W> {
+    fn drop(&mut self) {
+        if !self.finished && !std::thread::panicking() {
+            let _ = self.finish_stream();
+        }
+    }
 }
 
-pub fn decode_file(input_path: &str, output_path: &str, tranform_id: u8) {
-    let input_file = File::open(input_path).unwrap();
-    let mut reader = BufReader::new(input_file);
+pub fn encode_file(input_path: &str, output_path: &str, clear_dict_on_overfill: bool, pipeline: &[TransformStage],
+                   checksum: ChecksumSettings) -> Result<()> {
+    let input_file = File::open(input_path)?;
+    let writer = BufWriter::new(File::create(output_path)?);
 
-    let output_file = OpenOptions::new().write(true)
-                                        .create(true)
-                                        .truncate(true)
-                                        .open(output_path).unwrap();
-    let mut writer = BufWriter::new(output_file);
+    let original_size = regular_file_size(&input_file)?;
+    let mut encoder = LZWEncoder::with_original_size(writer, clear_dict_on_overfill, pipeline, checksum, original_size);
+    std::io::copy(&mut BufReader::new(input_file), &mut encoder)?;
+    encoder.finish()?;
+    Ok(())
+}
 
-    // Read first three bytes to restore parameters of encoder
-    let mut param_buff = [0u8; 3];
-    reader.read_exact(&mut param_buff).unwrap();
-    let clear_dict_on_overfill = param_buff[0] != 0;
-    let last_dict_index = u16::from_le_bytes(param_buff[1..3].try_into().unwrap());
+pub struct LZWDecoder<R: Read> {
+    input: BufReader<R>,
+    header: ContainerHeader,
+    internal_decoder: LZWCoderEnhanced,
+    transformer: InverseBlockTransformer,
+    checksum: StreamChecksum,
+    is_first: bool,
+    old_I: u16,
+    code_offset: u64,   // Position of the current code in the input
+    max_output_size: u64,
+    output: Vec<u8>,
+    output_pos: usize,
+    finished: bool,
+}
 
-    // Create decoder and initialize dictionary
-    let mut internal_decoder = LZWCoderEnhanced {
-        dict: Vec::new(),
-        reverse_dict_map: HashMap::new(),
-        max_dict_size: last_dict_index as usize + 1,    // We store only two bytes to ensure the limitation of max 16 bits for code
-        clear_dict_on_overfill,
-    };
-    internal_decoder.set_init_dict();
+impl<R: Read> LZWDecoder<R> {
+    pub fn new(input: R) -> Result<Self> {
+        Self::with_output_limit(input, DEFAULT_MAX_OUTPUT_SIZE)
+    }
 
-    let mut idx_buff = [0u8; 2];
-    let mut is_first = true;
-    let mut old_I = 0;
+    // Streams declaring more than max_output_size bytes are rejected before decoding,
+    // streams of unknown size as soon as they restore more
+    pub fn with_output_limit(input: R, max_output_size: u64) -> Result<Self> {
+        let mut input = BufReader::new(input);
 
-    let mut _output_buffer: Vec<u8> = Vec::new();
+        let header = ContainerHeader::read_from(&mut input)?;
+        if header.codec_id != CODEC_LZW {
+            return Err(Error::invalid_parameter("Stream is not LZW encoded"));
+        }
+        if header.original_size != UNKNOWN_SIZE {
+            header.check_output_limit(max_output_size)?;
+        }
 
-    let transform_buff_size = if tranform_id != 0 {if tranform_id == 3 { TRANSFORM_BLOCK_SIZE } else { BWT_RESULT_SIZE } } else { 0 };
+        // Read three bytes after the header to restore parameters of encoder
+        let mut param_buff = [0u8; 3];
+        input.read_exact(&mut param_buff).map_err(|err| Error::from(err).truncated_at(header.size() as u64))?;
+        let clear_dict_on_overfill = param_buff[0] != 0;
+        let last_dict_index = u16::from_le_bytes(param_buff[1..3].try_into().unwrap());
+
+        // Create decoder and initialize dictionary
+        let mut internal_decoder = LZWCoderEnhanced {
+            dict: Vec::new(),
+            reverse_dict_map: HashMap::new(),
+            max_dict_size: last_dict_index as usize + 1,    // We store only two bytes to ensure the limitation of max 16 bits for code
+            clear_dict_on_overfill,
+        };
+        internal_decoder.set_init_dict();
+
+        let code_offset = (header.size() + param_buff.len()) as u64;
+        Ok(LZWDecoder {
+            input,
+            transformer: InverseBlockTransformer::new(&header.pipeline, Some(header.original_size).filter(|&size| size != UNKNOWN_SIZE)),
+            checksum: StreamChecksum::new(ChecksumSettings::from_flags(header.flags)),
+            header,
+            internal_decoder,
+            is_first: true,
+            old_I: 0,
+            code_offset,
+            max_output_size,
+            output: Vec::new(),
+            output_pos: 0,
+            finished: false,
+        })
+    }
 
-    while let Some(_) = reader.read_exact(&mut idx_buff).ok() {
-        // Read next idx
-        let I = u16::from_le_bytes(idx_buff.try_into().unwrap());
+    pub fn header(&self) -> &ContainerHeader {
+        &self.header
+    }
 
+    // Decodes a single code, returns the sequence it stands for
+    fn decode_code(&mut self, I: u16) -> Result<Vec<u8>> {
         // First byte logic
-        if is_first {
-            is_first = false;
+        if self.is_first {
+            self.is_first = false;
+            self.old_I = I;
 
             // First byte should be always in the dict
-            if let Some((fb, _)) = internal_decoder.dict.get(I as usize) {
-                if tranform_id != 0 {
-                    _output_buffer.push(*fb);
-                } else {
-                    writer.write(&[*fb]).unwrap();   // Send it directly to output
-                }
-            } else {
-                panic!("Corrupted input data: first index not in dictionary");
-            }
-
-            old_I = I;
-            continue;
+            return match self.internal_decoder.dict.get(I as usize) {
+                Some(&(fb, _)) => Ok(vec![fb]),
+                None => Err(Error::corrupt(self.code_offset, "First index not in dictionary")),
+            };
         }
 
         // Check clear symbol
         if I == CLEAR_SYMBOL {
-            internal_decoder.set_init_dict();
-            is_first = true;
-            continue;
+            self.internal_decoder.set_init_dict();
+            self.is_first = true;
+            return Ok(Vec::new());
         }
 
         // Normal processing
-        if let Some(S) = internal_decoder.recover_seq_from_dict(I) {
-            if tranform_id != 0 {
-                _output_buffer.extend_from_slice(&S);
-            } else {
-                writer.write(&S).unwrap();
-            }
+        if let Some(S) = self.internal_decoder.recover_seq_from_dict(I) {
+            self.internal_decoder.add_seq_to_dict((S[0], Some(self.old_I)));
+            self.old_I = I;
+            Ok(S)
+        } else if I as usize != self.internal_decoder.dict.len() || self.internal_decoder.dict.len() >= self.internal_decoder.max_dict_size {
+            Err(Error::corrupt(self.code_offset, format!("Index {} not in dictionary of {} entries", I, self.internal_decoder.dict.len())))
+        } else if let Some(mut S) = self.internal_decoder.recover_seq_from_dict(self.old_I) {
+            // Special case (only case when I is not in dict - covering sequences, I is the next index to be added)
+            // S = old_S || old_S[0]
+            S.push(S[0]);
 
-            internal_decoder.add_seq_to_dict((S[0], Some(old_I)));
-            old_I = I;
+            // Add this sequence to the dict
+            self.internal_decoder.add_seq_to_dict((S[0], Some(self.old_I)));
+
+            // Set I to newly added sequence
+            self.old_I = self.internal_decoder.get_last_dict_index();
+            Ok(S)
         } else {
-            // Special case (only case when I is not in dict - covering sequences)
-            // S = old_S || old_S[0]
-            if let Some(old_S) = internal_decoder.recover_seq_from_dict(old_I) {
-                if tranform_id != 0 {
-                    _output_buffer.extend_from_slice(&old_S);
-                    _output_buffer.push(old_S[0]);
-                } else {
-                    writer.write(&old_S).unwrap();
-                    writer.write(&old_S[0..1]).unwrap();
-                }
+            Err(Error::corrupt(self.code_offset, "Index not in dictionary"))
+        }
+    }
 
-                // Add this sequence to the dict
-                internal_decoder.add_seq_to_dict((old_S[0], Some(old_I)));
+    // Decodes codes until some output is restored or the stream ends
+    fn decode_chunk(&mut self) -> Result<()> {
+        let mut idx_buff = [0u8; 2];
+        self.output.clear();
+        self.output_pos = 0;
 
-                // Set I to newly added sequence
-                old_I = internal_decoder.get_last_dict_index();
+        while self.output.is_empty() && !self.transformer.is_complete() {
+            self.input.read_exact(&mut idx_buff).map_err(|err| Error::from(err).truncated_at(self.code_offset))?;
+            let I = u16::from_le_bytes(idx_buff);
+
+            // End symbol is only written to streams of unknown size
+            if I == END_SYMBOL && self.header.original_size == UNKNOWN_SIZE {
+                self.code_offset += idx_buff.len() as u64;
+                self.output = self.transformer.finish()?;
+                break;
             }
+
+            let sequence = self.decode_code(I)?;
+            self.code_offset += idx_buff.len() as u64;
+            self.output = self.transformer.push(&sequence)?;
         }
-        
-        if tranform_id != 0 {
-            // Flush transformation slice if it reached BWT_RESULT_SIZE
-            while _output_buffer.len() >= transform_buff_size {
-                let to_process = &_output_buffer.drain(0..transform_buff_size).collect();
-                let detransformed = perform_inverse_transform(to_process, tranform_id);
-                writer.write(&detransformed).unwrap();
+        self.checksum.update(&self.output);
+
+        if self.transformer.restored_size() > self.max_output_size {
+            return Err(Error::LimitExceeded { size: self.transformer.restored_size(), limit: self.max_output_size });
+        }
+
+        if self.transformer.is_complete() {
+            self.finish_stream()?;
+        }
+
+        Ok(())
+    }
+
+    fn finish_stream(&mut self) -> Result<()> {
+        self.finished = true;
+
+        // Size of streams of unknown size follows the end code
+        let size_offset = self.code_offset;
+        let original_size = if self.header.original_size == UNKNOWN_SIZE {
+            let mut size_buff = [0u8; 8];
+            self.input.read_exact(&mut size_buff).map_err(|err| Error::from(err).truncated_at(self.code_offset))?;
+            self.code_offset += size_buff.len() as u64;
+            u64::from_le_bytes(size_buff)
+        } else {
+            self.header.original_size
+        };
+
+        if self.transformer.restored_size() != original_size {
+            return Err(Error::corrupt(size_offset, "Decoded size does not match the stored size"));
+        }
+
+        // Trailer directly follows the last code or the size
+        let settings = ChecksumSettings::from_flags(self.header.flags);
+        let expected = ChecksumTrailer::read_from(&mut self.input, settings, original_size)
+                                      .map_err(|err| Error::from(err).truncated_at(self.code_offset))?;
+        let actual = std::mem::replace(&mut self.checksum, StreamChecksum::new(settings)).finish();
+
+        verify(&expected, &actual)
+    }
+}
+
+impl<R: Read> Read for LZWDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        while self.output_pos == self.output.len() && !self.finished {
+            self.decode_chunk()?;
+        }
+
+        let bytes_to_copy = buf.len().min(self.output.len() - self.output_pos);
+        buf[..bytes_to_copy].copy_from_slice(&self.output[self.output_pos..self.output_pos + bytes_to_copy]);
+        self.output_pos += bytes_to_copy;
+
+        Ok(bytes_to_copy)
+    }
+}
+
+pub fn decode_file(input_path: &str, output_path: &str) -> Result<()> {
+    let mut decoder = LZWDecoder::new(File::open(input_path)?)?;
+    let mut writer = BufWriter::new(File::create(output_path)?);
+
+    std::io::copy(&mut decoder, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub struct LZWCodec {
+    pub clear_dict_on_overfill: bool,   // Otherwise the dictionary stays frozen once full
+}
+
+impl Codec for LZWCodec {
+    fn name(&self) -> &'static str {
+        "lzw"
+    }
+
+    fn id(&self) -> u8 {
+        CODEC_LZW
+    }
+
+    fn encode(&self, input: &mut dyn Read, output: &mut dyn Write, parameters: &CodecParameters) -> Result<()> {
+        let mut encoder = LZWEncoder::with_original_size(output, self.clear_dict_on_overfill, parameters.pipeline, parameters.checksum,
+                                                         parameters.original_size);
+        std::io::copy(input, &mut encoder)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    fn open_decoder<'a>(&self, input: Box<dyn Read + 'a>, max_output_size: u64) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(LZWDecoder::with_output_limit(input, max_output_size)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Checksum::ChecksumKind;
+
+    fn sample_data() -> Vec<u8> {
+        (0..2 * TRANSFORM_BLOCK_SIZE + 5000).map(|i| ((i % 251) ^ (i / 1000)) as u8).collect()
+    }
+
+    #[test]
+    fn adapters_handle_odd_sized_writes_and_single_byte_reads() {
+        let data = sample_data();
+        let checksum = ChecksumSettings::new(ChecksumKind::CRC32, true);
+
+        for transform_id in [0, 1, 7] {
+            let mut encoder = LZWEncoder::new(Vec::new(), true, &transform_pipeline(transform_id).unwrap(), checksum);
+            let mut remaining = data.as_slice();
+            for chunk_size in [1, 7, 4093, 65537].iter().cycle() {
+                if remaining.is_empty() {
+                    break;
+                }
+                let (chunk, rest) = remaining.split_at((*chunk_size).min(remaining.len()));
+                encoder.write_all(chunk).unwrap();
+                remaining = rest;
             }
+            let compressed = encoder.finish().unwrap();
+
+            let mut decoder = LZWDecoder::new(compressed.as_slice()).unwrap();
+            let mut restored = Vec::new();
+            let mut byte = [0u8; 1];
+            while decoder.read(&mut byte).unwrap() == 1 {
+                restored.push(byte[0]);
+            }
+            assert!(restored == data, "transform {}", transform_id);
         }
     }
 
-    if tranform_id != 0 {
-        // Flush remaining transformation
-        if _output_buffer.len() > 0 {
-            writer.write(&perform_inverse_MTF_BWT(&_output_buffer)).unwrap();
+    #[test]
+    fn codes_are_written_before_finish() {
+        let data = sample_data();
+        let checksum = ChecksumSettings::new(ChecksumKind::CRC32, false);
+
+        let mut encoder = LZWEncoder::new(Vec::new(), true, &[], checksum);
+        encoder.write_all(&data).unwrap();
+        let written = encoder.output.as_ref().unwrap().len();
+        assert!(written > data.len() / 10, "only {} bytes written", written);
+
+        let compressed = encoder.finish().unwrap();
+        let header = ContainerHeader::read_from(&mut compressed.as_slice()).unwrap();
+        assert_eq!(header.original_size, UNKNOWN_SIZE);
+
+        let mut restored = Vec::new();
+        LZWDecoder::new(compressed.as_slice()).unwrap().read_to_end(&mut restored).unwrap();
+        assert!(restored == data);
+    }
+
+    #[test]
+    fn size_known_in_advance_is_recorded_in_header() {
+        let data = sample_data();
+        let checksum = ChecksumSettings::new(ChecksumKind::CRC32, true);
+        let pipeline = transform_pipeline(1).unwrap();
+
+        let mut encoder = LZWEncoder::with_original_size(Vec::new(), true, &pipeline, checksum, Some(data.len() as u64));
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let header = ContainerHeader::read_from(&mut compressed.as_slice()).unwrap();
+        assert_eq!(header.original_size, data.len() as u64);
+        let mut restored = Vec::new();
+        LZWDecoder::new(compressed.as_slice()).unwrap().read_to_end(&mut restored).unwrap();
+        assert!(restored == data);
+
+        let mut encoder = LZWEncoder::with_original_size(Vec::new(), true, &pipeline, checksum, Some(data.len() as u64 + 1));
+        encoder.write_all(&data).unwrap();
+        assert!(matches!(encoder.finish(), Err(Error::InvalidParameter(_))));
+    }
+
+    #[test]
+    fn dropped_encoder_finishes_the_stream() {
+        let data = sample_data();
+        let mut compressed = Vec::new();
+
+        let mut encoder = LZWEncoder::new(&mut compressed, false, &transform_pipeline(1).unwrap(), ChecksumSettings::new(ChecksumKind::XXH64, false));
+        encoder.write_all(&data).unwrap();
+        drop(encoder);
+
+        let mut restored = Vec::new();
+        LZWDecoder::new(compressed.as_slice()).unwrap().read_to_end(&mut restored).unwrap();
+        assert!(restored == data);
+    }
+
+    #[test]
+    fn streams_with_size_in_header_decode() {
+        // Header declaring the size is followed by the codes and the checksum trailer directly
+        let checksum = ChecksumSettings::new(ChecksumKind::None, false);
+        let mut header = ContainerHeader::new(CODEC_LZW, 4, &[]);
+        header.flags = checksum.to_flags();
+
+        let mut compressed = header.to_bytes();
+        compressed.extend_from_slice(&[1, 0xFF, 0xFF]);
+        for code in [b'a' as u16, b'b' as u16, 256] {
+            compressed.extend_from_slice(&code.to_le_bytes());
         }
+
+        let mut restored = Vec::new();
+        LZWDecoder::new(compressed.as_slice()).unwrap().read_to_end(&mut restored).unwrap();
+        assert_eq!(restored, b"abab");
+    }
+
+    #[test]
+    fn stored_size_has_to_match_decoded_data() {
+        let checksum = ChecksumSettings::new(ChecksumKind::None, false);
+        let mut compressed = Vec::new();
+        let mut encoder = LZWEncoder::new(&mut compressed, true, &[], checksum);
+        encoder.write_all(b"abab").unwrap();
+        encoder.finish().unwrap();
+
+        // Size directly follows the end code at the end of the stream
+        let size_offset = compressed.len() - 8;
+        assert_eq!(compressed[size_offset - 2..size_offset], END_SYMBOL.to_le_bytes());
+        compressed[size_offset] = 5;
+
+        let mut restored = Vec::new();
+        let result = LZWDecoder::new(compressed.as_slice()).unwrap().read_to_end(&mut restored).map_err(Error::from);
+        assert!(matches!(result, Err(Error::CorruptData { offset, .. }) if offset == size_offset as u64), "{:?}", result);
     }
 }
@@ -0,0 +1,156 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::Checksum::ChecksumSettings;
+use crate::Container;
+use crate::Error::{Error, Result};
+use crate::TransformationMethods::{TransformStage, TRANSFORM_BLOCK_SIZE};
+
+// Blocks taken from the input for trial compression
+pub const SAMPLE_BLOCK_COUNT: usize = 8;
+
+// Order-0 entropy in bits per byte above which data is taken as already compressed, only
+// pipelines without transforms are tried then
+const INCOMPRESSIBLE_ENTROPY: f64 = 7.9;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodChoice {
+    pub codec_id: u8,
+    pub pipeline: Vec<TransformStage>,
+    pub estimated_size: u64,    // Compressed size of the whole input extrapolated from the sample
+}
+
+// Order-0 entropy in bits per byte
+pub fn entropy(data: &[u8]) -> f64 {
+    let mut freq_t = [0u64; 256];
+    for &byte in data.iter() {
+        freq_t[byte as usize] += 1;
+    }
+
+    freq_t.iter().filter(|&&freq| freq != 0).map(|&freq| {
+        let probability = freq as f64 / data.len() as f64;
+        -probability * probability.log2()
+    }).sum()
+}
+
+// Blocks spread evenly over the input (all of it when small), the input is left at its start
+pub fn sample_input<R: Read + Seek>(input: &mut R, block_count: usize) -> Result<Vec<u8>> {
+    let size = input.seek(SeekFrom::End(0))?;
+    let mut sample = Vec::new();
+
+    if size <= (block_count * TRANSFORM_BLOCK_SIZE) as u64 {
+        input.seek(SeekFrom::Start(0))?;
+        input.read_to_end(&mut sample)?;
+    } else {
+        let last_block_offset = size - TRANSFORM_BLOCK_SIZE as u64;
+        for block_id in 0..block_count as u64 {
+            input.seek(SeekFrom::Start(last_block_offset * block_id / (block_count as u64 - 1).max(1)))?;
+            input.take(TRANSFORM_BLOCK_SIZE as u64).read_to_end(&mut sample)?;
+        }
+    }
+
+    input.seek(SeekFrom::Start(0))?;
+    Ok(sample)
+}
+
+// Trial compression of the sample with every candidate, the smallest estimate for input_size bytes wins
+// (first one on a tie, so candidates should be ordered from the cheapest)
+pub fn select_method(sample: &[u8], input_size: u64, codec_ids: &[u8], pipelines: &[Vec<TransformStage>]) -> Result<MethodChoice> {
+    let pipelines: Vec<&Vec<TransformStage>> = if entropy(sample) > INCOMPRESSIBLE_ENTROPY && pipelines.iter().any(|p| p.is_empty()) {
+        pipelines.iter().filter(|pipeline| pipeline.is_empty()).collect()
+    } else {
+        pipelines.iter().collect()
+    };
+
+    let mut best: Option<MethodChoice> = None;
+    for &codec_id in codec_ids.iter() {
+        for &pipeline in pipelines.iter() {
+            // Header, tables and trailer do not grow with the input
+            let fixed_size = Container::compress(&[], codec_id, pipeline, ChecksumSettings::NONE)?.len() as u64;
+            let trial_size = Container::compress(sample, codec_id, pipeline, ChecksumSettings::NONE)?.len() as u64;
+
+            let estimated_size = if sample.is_empty() {
+                fixed_size
+            } else {
+                fixed_size + (trial_size.saturating_sub(fixed_size) as f64 * input_size as f64 / sample.len() as f64) as u64
+            };
+
+            if best.as_ref().is_none_or(|best| estimated_size < best.estimated_size) {
+                best = Some(MethodChoice { codec_id, pipeline: pipeline.clone(), estimated_size });
+            }
+        }
+    }
+
+    best.ok_or_else(|| Error::invalid_parameter("No codec or pipeline to select from"))
+}
+
+// Samples the input and selects a method for all of it, the input is left at its start
+pub fn choose_method<R: Read + Seek>(input: &mut R, codec_ids: &[u8], pipelines: &[Vec<TransformStage>]) -> Result<MethodChoice> {
+    let sample = sample_input(input, SAMPLE_BLOCK_COUNT)?;
+    let input_size = input.seek(SeekFrom::End(0))?;
+    input.seek(SeekFrom::Start(0))?;
+
+    select_method(&sample, input_size, codec_ids, pipelines)
+}
+
+#[cfg(all(test, any(feature = "huffman", feature = "lzw")))]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::Codec::registered_codecs;
+    use crate::TransformationMethods::preset_pipelines;
+
+    fn codec_ids() -> Vec<u8> {
+        registered_codecs().iter().map(|codec| codec.id()).collect()
+    }
+
+    // Deterministic noise, xorshift
+    fn noise(size: usize) -> Vec<u8> {
+        let mut state = 0x2545f4914f6cdd1du64;
+        (0..size).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 32) as u8
+        }).collect()
+    }
+
+    #[test]
+    fn entropy_of_constant_and_uniform_data() {
+        assert_eq!(entropy(&[7; 100]), 0.0);
+        assert_eq!(entropy(&(0..=255).collect::<Vec<u8>>()), 8.0);
+        assert_eq!(entropy(&[]), 0.0);
+    }
+
+    #[test]
+    fn samples_are_spread_over_the_input() {
+        let data: Vec<u8> = (0..100 * TRANSFORM_BLOCK_SIZE).map(|i| (i / TRANSFORM_BLOCK_SIZE) as u8).collect();
+        let mut input = Cursor::new(data);
+
+        let sample = sample_input(&mut input, 4).unwrap();
+        assert_eq!(sample.len(), 4 * TRANSFORM_BLOCK_SIZE);
+        assert_eq!((sample[0], sample[sample.len() - 1]), (0, 99));
+        assert_eq!(input.position(), 0);
+
+        let mut small_input = Cursor::new(b"banana".to_vec());
+        assert_eq!(sample_input(&mut small_input, 4).unwrap(), b"banana");
+    }
+
+    #[test]
+    fn noise_is_stored_without_transforms() {
+        let data = noise(64 << 10);
+        let choice = choose_method(&mut Cursor::new(&data), &codec_ids(), &preset_pipelines()).unwrap();
+        assert!(choice.pipeline.is_empty());
+    }
+
+    #[test]
+    fn chosen_method_beats_plain_coding_of_text() {
+        let data = b"the quick brown fox jumps over the lazy dog; ".repeat(500);
+        let choice = choose_method(&mut Cursor::new(&data), &codec_ids(), &preset_pipelines()).unwrap();
+
+        let compressed = Container::compress(&data, choice.codec_id, &choice.pipeline, ChecksumSettings::NONE).unwrap();
+        for codec_id in codec_ids() {
+            assert!(compressed.len() <= Container::compress(&data, codec_id, &[], ChecksumSettings::NONE).unwrap().len());
+        }
+        assert_eq!(Container::decompress(&compressed).unwrap(), data);
+    }
+}
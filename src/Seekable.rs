@@ -0,0 +1,336 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+
+use crate::Checksum::ChecksumSettings;
+use crate::Container;
+use crate::Error::{Error, Result};
+use crate::TransformationMethods::TransformStage;
+
+// Layout: magic | version | flags | frames (complete compressed streams) | index | footer
+pub const SEEKABLE_MAGIC: [u8; 4] = *b"BSLS";
+pub const INDEX_MAGIC: [u8; 4] = *b"BSLI";
+pub const SEEKABLE_VERSION: u8 = 1;
+
+// Default size of independently compressed blocks (1MB)
+pub const SEEKABLE_BLOCK_SIZE: usize = 1 << 20;
+
+const FILE_HEADER_SIZE: u64 = 6;
+// Frame count (u32) | block size (u32) | index offset (u64) | index magic (4)
+const FOOTER_SIZE: u64 = 20;
+const INDEX_ENTRY_SIZE: usize = 24;
+// Memory reserved up front for a block
+const MAX_BLOCK_RESERVE: usize = 1 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameIndexEntry {
+    pub uncompressed_offset: u64,
+    pub compressed_offset: u64,     // From the start of the file
+    pub compressed_size: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeekableIndex {
+    pub block_size: u32,
+    pub frames: Vec<FrameIndexEntry>,
+    pub total_size: u64,
+}
+
+impl SeekableIndex {
+    fn frame_size(&self, frame_id: usize) -> u64 {
+        let next_offset = self.frames.get(frame_id + 1).map_or(self.total_size, |f| f.uncompressed_offset);
+        next_offset - self.frames[frame_id].uncompressed_offset
+    }
+}
+
+pub fn compress_seekable(input_path: &str, output_path: &str, block_size: usize, codec_id: u8,
+                         pipeline: &[TransformStage], checksum: ChecksumSettings) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(output_path)?);
+    compress_seekable_stream(&mut File::open(input_path)?, &mut writer, block_size, codec_id, pipeline, checksum)
+}
+
+// Output is written sequentially, so it does not need to be seekable
+pub fn compress_seekable_stream<R: Read, W: Write>(input: &mut R, writer: &mut W, block_size: usize, codec_id: u8,
+                                                   pipeline: &[TransformStage], checksum: ChecksumSettings) -> Result<()> {
+    writer.write_all(&SEEKABLE_MAGIC)?;
+    writer.write_all(&[SEEKABLE_VERSION, 0])?;
+
+    let mut frames: Vec<FrameIndexEntry> = Vec::new();
+    let mut compressed_offset = FILE_HEADER_SIZE;
+    let mut uncompressed_offset = 0;
+    // Block size may be up to 4GB, so the buffer grows with the input beyond the reserve
+    let mut block = Vec::with_capacity(block_size.min(MAX_BLOCK_RESERVE));
+
+    loop {
+        block.clear();
+        input.take(block_size as u64).read_to_end(&mut block)?;
+        if block.is_empty() {
+            break;  // EOF
+        }
+
+        // Every block is a complete compressed stream, so it can be decoded on its own
+        let frame = Container::compress(&block, codec_id, pipeline, checksum)?;
+        writer.write_all(&frame)?;
+
+        let compressed_size = frame.len() as u64;
+        frames.push(FrameIndexEntry { uncompressed_offset, compressed_offset, compressed_size });
+
+        uncompressed_offset += block.len() as u64;
+        compressed_offset += compressed_size;
+    }
+
+    // Index: uncompressed offset | compressed offset | compressed size (u64 each), the last entry marks total size
+    for frame in frames.iter() {
+        writer.write_all(&frame.uncompressed_offset.to_le_bytes())?;
+        writer.write_all(&frame.compressed_offset.to_le_bytes())?;
+        writer.write_all(&frame.compressed_size.to_le_bytes())?;
+    }
+    writer.write_all(&uncompressed_offset.to_le_bytes())?;
+    writer.write_all(&compressed_offset.to_le_bytes())?;
+    writer.write_all(&0u64.to_le_bytes())?;
+
+    writer.write_all(&(frames.len() as u32).to_le_bytes())?;
+    writer.write_all(&(block_size as u32).to_le_bytes())?;
+    writer.write_all(&compressed_offset.to_le_bytes())?;
+    writer.write_all(&INDEX_MAGIC)?;
+
+    Ok(writer.flush()?)
+}
+
+pub fn read_index(input_path: &str) -> Result<SeekableIndex> {
+    read_index_from(&mut File::open(input_path)?)
+}
+
+pub fn read_index_from<R: Read + Seek>(file: &mut R) -> Result<SeekableIndex> {
+    let file_size = file.seek(SeekFrom::End(0))?;
+    if file_size < FILE_HEADER_SIZE + FOOTER_SIZE {
+        return Err(Error::corrupt(file_size, "Seekable stream is truncated"));
+    }
+    file.seek(SeekFrom::Start(0))?;
+
+    let mut header = [0u8; FILE_HEADER_SIZE as usize];
+    file.read_exact(&mut header)?;
+    if header[0..4] != SEEKABLE_MAGIC {
+        return Err(Error::corrupt(0, "Not a seekable stream: invalid magic bytes"));
+    }
+    if header[4] != SEEKABLE_VERSION {
+        return Err(Error::UnsupportedVersion { format: "seekable format", version: header[4] });
+    }
+
+    let mut footer = [0u8; FOOTER_SIZE as usize];
+    let footer_offset = file.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+    file.read_exact(&mut footer)?;
+    if footer[16..20] != INDEX_MAGIC {
+        return Err(Error::corrupt(footer_offset, "Seekable index is missing or damaged"));
+    }
+
+    let frame_count = u32::from_le_bytes(footer[0..4].try_into().unwrap()) as u64;
+    let block_size = u32::from_le_bytes(footer[4..8].try_into().unwrap());
+    let index_offset = u64::from_le_bytes(footer[8..16].try_into().unwrap());
+
+    // Index lies right before the footer, so its size is checked before anything is allocated
+    let index_size = (frame_count + 1) * INDEX_ENTRY_SIZE as u64;
+    if index_offset < FILE_HEADER_SIZE || index_offset.checked_add(index_size) != Some(footer_offset) {
+        return Err(Error::corrupt(footer_offset, "Seekable index does not fit into the stream"));
+    }
+
+    let mut index_bytes = vec![0u8; index_size as usize];
+    file.seek(SeekFrom::Start(index_offset))?;
+    file.read_exact(&mut index_bytes)?;
+
+    let mut frames: Vec<FrameIndexEntry> = index_bytes.chunks_exact(INDEX_ENTRY_SIZE).map(|entry| FrameIndexEntry {
+        uncompressed_offset: u64::from_le_bytes(entry[0..8].try_into().unwrap()),
+        compressed_offset: u64::from_le_bytes(entry[8..16].try_into().unwrap()),
+        compressed_size: u64::from_le_bytes(entry[16..24].try_into().unwrap()),
+    }).collect();
+
+    let total_size = frames.pop().unwrap().uncompressed_offset;
+    let index = SeekableIndex { block_size, frames, total_size };
+    validate_index(&index, index_offset)?;
+    Ok(index)
+}
+
+// Frames have to follow each other in both uncompressed and compressed data, none larger than the block size
+fn validate_index(index: &SeekableIndex, index_offset: u64) -> Result<()> {
+    let mut uncompressed_offset = 0;
+    let mut compressed_offset = FILE_HEADER_SIZE;
+
+    for (frame_id, frame) in index.frames.iter().enumerate() {
+        let frame_end = frame.compressed_offset.checked_add(frame.compressed_size);
+        if frame.uncompressed_offset != uncompressed_offset || frame.compressed_offset != compressed_offset
+           || frame_end.is_none_or(|end| end > index_offset) {
+            return Err(Error::corrupt(index_offset + (frame_id * INDEX_ENTRY_SIZE) as u64, format!("Invalid index entry of frame {}", frame_id)));
+        }
+
+        let next_offset = index.frames.get(frame_id + 1).map_or(index.total_size, |f| f.uncompressed_offset);
+        let frame_size = next_offset.saturating_sub(uncompressed_offset);
+        if frame_size == 0 || frame_size > index.block_size as u64 {
+            return Err(Error::corrupt(index_offset + (frame_id * INDEX_ENTRY_SIZE) as u64, format!("Invalid size of frame {}", frame_id)));
+        }
+
+        uncompressed_offset += frame_size;
+        compressed_offset += frame.compressed_size;
+    }
+
+    if uncompressed_offset != index.total_size {
+        return Err(Error::corrupt(index_offset, "Seekable index total size does not match its frames"));
+    }
+    Ok(())
+}
+
+// Frame can not restore more than its size in the index
+fn decode_frame<R: Read + Seek>(file: &mut R, frame: &FrameIndexEntry, frame_size: u64) -> Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(frame.compressed_offset))?;
+
+    let mut block = Vec::new();
+    Container::open_decoder_with_limit(file.take(frame.compressed_size), frame_size)?.read_to_end(&mut block)?;
+    Ok(block)
+}
+
+// Decompresses only the blocks overlapping [offset, offset + length), range is clamped to the data size
+pub fn read_range(input_path: &str, offset: u64, length: u64) -> Result<Vec<u8>> {
+    read_range_from(&mut File::open(input_path)?, offset, length)
+}
+
+pub fn read_range_from<R: Read + Seek>(file: &mut R, offset: u64, length: u64) -> Result<Vec<u8>> {
+    let index = read_index_from(file)?;
+    let end = offset.saturating_add(length).min(index.total_size);
+
+    let mut result = Vec::new();
+    if offset >= end {
+        return Ok(result);
+    }
+
+    // First frame containing offset
+    let first_frame = index.frames.partition_point(|frame| frame.uncompressed_offset <= offset) - 1;
+
+    for (frame_id, frame) in index.frames.iter().enumerate().skip(first_frame) {
+        if frame.uncompressed_offset >= end {
+            break;
+        }
+
+        let block = decode_frame(file, frame, index.frame_size(frame_id))?;
+        if block.len() as u64 != index.frame_size(frame_id) {
+            return Err(Error::corrupt(frame.compressed_offset, format!("Frame {} decoded to unexpected size", frame_id)));
+        }
+
+        let from = offset.saturating_sub(frame.uncompressed_offset) as usize;
+        let to = (end - frame.uncompressed_offset).min(block.len() as u64) as usize;
+        result.extend_from_slice(&block[from..to]);
+    }
+
+    Ok(result)
+}
+
+pub fn decompress_seekable(input_path: &str, output_path: &str) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(output_path)?);
+    decompress_seekable_stream(&mut File::open(input_path)?, &mut writer)
+}
+
+pub fn decompress_seekable_stream<R: Read + Seek, W: Write>(file: &mut R, writer: &mut W) -> Result<()> {
+    let index = read_index_from(file)?;
+
+    for (frame_id, frame) in index.frames.iter().enumerate() {
+        let frame_size = index.frame_size(frame_id);
+        let block = decode_frame(file, frame, frame_size)?;
+        if block.len() as u64 != frame_size {
+            return Err(Error::corrupt(frame.compressed_offset, format!("Frame {} decoded to unexpected size", frame_id)));
+        }
+
+        writer.write_all(&block)?;
+    }
+
+    Ok(writer.flush()?)
+}
+
+#[cfg(all(test, any(feature = "huffman", feature = "lzw")))]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::Checksum::ChecksumKind;
+    use crate::Codec::registered_codecs;
+    use crate::TransformationMethods::transform_pipeline;
+
+    const BLOCK_SIZE: usize = 1000;
+
+    fn sample_data() -> Vec<u8> {
+        (0..10_500u64).map(|i| (i * i % 251) as u8).collect()
+    }
+
+    fn seekable_stream(data: &[u8]) -> Vec<u8> {
+        let mut stream = Vec::new();
+        let checksum = ChecksumSettings::new(ChecksumKind::CRC32, true);
+        compress_seekable_stream(&mut &data[..], &mut stream, BLOCK_SIZE, registered_codecs()[0].id(),
+                                 &transform_pipeline(1).unwrap(), checksum).unwrap();
+        stream
+    }
+
+    fn read_range(stream: &[u8], offset: u64, length: u64) -> Result<Vec<u8>> {
+        read_range_from(&mut Cursor::new(stream), offset, length)
+    }
+
+    #[test]
+    fn ranges_are_clamped_to_data() {
+        let data = sample_data();
+        let stream = seekable_stream(&data);
+
+        // Across one and several frame boundaries
+        assert_eq!(read_range(&stream, 950, 100).unwrap(), data[950..1050]);
+        assert_eq!(read_range(&stream, 999, 2002).unwrap(), data[999..3001]);
+        assert_eq!(read_range(&stream, 2000, 1000).unwrap(), data[2000..3000]);
+
+        // Past the end of data
+        assert_eq!(read_range(&stream, 10_400, 1000).unwrap(), data[10_400..]);
+        assert_eq!(read_range(&stream, 0, u64::MAX).unwrap(), data);
+        assert!(read_range(&stream, 20_000, 10).unwrap().is_empty());
+
+        // Zero length
+        assert!(read_range(&stream, 0, 0).unwrap().is_empty());
+        assert!(read_range(&stream, 1500, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn index_describes_all_frames() {
+        let data = sample_data();
+        let index = read_index_from(&mut Cursor::new(seekable_stream(&data))).unwrap();
+
+        assert_eq!(index.frames.len(), data.len().div_ceil(BLOCK_SIZE));
+        assert_eq!(index.total_size, data.len() as u64);
+        assert_eq!(index.frame_size(index.frames.len() - 1), 500);
+    }
+
+    #[test]
+    fn invalid_indexes_are_rejected() {
+        let stream = seekable_stream(&sample_data());
+        let index = read_index_from(&mut Cursor::new(&stream)).unwrap();
+        let index_offset = stream.len() as u64 - FOOTER_SIZE - (index.frames.len() as u64 + 1) * INDEX_ENTRY_SIZE as u64;
+        let entry_offset = |frame_id: u64| index_offset + frame_id * INDEX_ENTRY_SIZE as u64;
+
+        // Frames out of order make the previous frame span two blocks
+        let mut swapped = index.clone();
+        swapped.frames.swap(2, 3);
+        assert!(matches!(validate_index(&swapped, index_offset), Err(Error::CorruptData { offset, .. }) if offset == entry_offset(1)));
+
+        let mut overlapping = index.clone();
+        overlapping.frames[4].compressed_offset -= 1;
+        assert!(matches!(validate_index(&overlapping, index_offset), Err(Error::CorruptData { offset, .. }) if offset == entry_offset(4)));
+
+        let mut oversized = index.clone();
+        oversized.total_size += BLOCK_SIZE as u64;
+        assert!(matches!(validate_index(&oversized, index_offset), Err(Error::CorruptData { offset, .. }) if offset == entry_offset(10)));
+
+        let mut past_index = index.clone();
+        past_index.frames.last_mut().unwrap().compressed_size += 1;
+        assert!(validate_index(&past_index, index_offset).is_err());
+
+        // Frame count in the footer larger than the stored index
+        let mut missing_entries = stream.clone();
+        let count_pos = stream.len() - FOOTER_SIZE as usize;
+        missing_entries[count_pos] += 1;
+        assert!(matches!(read_index_from(&mut Cursor::new(missing_entries)), Err(Error::CorruptData { .. })));
+
+        // Stream cut inside the index or the footer
+        for length in [stream.len() - 1, stream.len() - FOOTER_SIZE as usize - 5, 10] {
+            assert!(matches!(read_index_from(&mut Cursor::new(&stream[..length])), Err(Error::CorruptData { .. })), "length {}", length);
+        }
+    }
+}
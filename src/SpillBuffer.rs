@@ -0,0 +1,184 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Data above this size is moved from memory to a temporary file (32MB)
+pub const SPILL_THRESHOLD: usize = 32 << 20;
+
+static SPILL_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Temporary file is removed when it is no longer used
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn create() -> Result<(Self, File), std::io::Error> {
+        let file_id = SPILL_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("bsl-spill-{}-{}.tmp", std::process::id(), file_id));
+
+        let file = OpenOptions::new().read(true)
+                                     .write(true)
+                                     .create_new(true)
+                                     .open(&path)?;
+        Ok((TempFile { path }, file))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// Collects data that has to be read again later (two-pass codecs, non-seekable inputs),
+// keeps it in memory until the threshold is reached
+pub struct SpillBuffer {
+    memory: Vec<u8>,
+    file: Option<(TempFile, BufWriter<File>)>,
+    threshold: usize,
+    len: u64,
+}
+
+impl Default for SpillBuffer {
+    fn default() -> Self {
+        SpillBuffer::new()
+    }
+}
+
+impl SpillBuffer {
+    pub fn new() -> Self {
+        SpillBuffer::with_threshold(SPILL_THRESHOLD)
+    }
+
+    pub fn with_threshold(threshold: usize) -> Self {
+        SpillBuffer {
+            memory: Vec::new(),
+            file: None,
+            threshold,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_spilled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    // Returns collected data from the start
+    pub fn into_reader(self) -> Result<SpillReader, std::io::Error> {
+        match self.file {
+            None => Ok(SpillReader::Memory(Cursor::new(self.memory))),
+            Some((temp_file, writer)) => {
+                let mut file = writer.into_inner().map_err(|err| err.into_error())?;
+                file.seek(SeekFrom::Start(0))?;
+                Ok(SpillReader::File(BufReader::new(file), temp_file))
+            }
+        }
+    }
+}
+
+impl Write for SpillBuffer {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        if self.file.is_none() && self.memory.len() + buf.len() > self.threshold {
+            let (temp_file, file) = TempFile::create()?;
+            let mut writer = BufWriter::new(file);
+            writer.write_all(&self.memory)?;
+
+            self.memory = Vec::new();
+            self.file = Some((temp_file, writer));
+        }
+
+        match self.file {
+            Some((_, ref mut writer)) => writer.write_all(buf)?,
+            None => self.memory.extend_from_slice(buf),
+        }
+
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        match self.file {
+            Some((_, ref mut writer)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+pub enum SpillReader {
+    Memory(Cursor<Vec<u8>>),
+    File(BufReader<File>, TempFile),
+}
+
+impl Read for SpillReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        match self {
+            SpillReader::Memory(cursor) => cursor.read(buf),
+            SpillReader::File(reader, _) => reader.read(buf),
+        }
+    }
+}
+
+impl Seek for SpillReader {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, std::io::Error> {
+        match self {
+            SpillReader::Memory(cursor) => cursor.seek(pos),
+            SpillReader::File(reader, _) => reader.seek(pos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spills_to_file_past_threshold() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i * 7 % 251) as u8).collect();
+
+        let mut buffer = SpillBuffer::with_threshold(1000);
+        for chunk in data.chunks(333) {
+            buffer.write_all(chunk).unwrap();
+        }
+        assert!(buffer.is_spilled());
+        assert_eq!(buffer.len(), data.len() as u64);
+
+        let mut reader = buffer.into_reader().unwrap();
+        let path = match reader {
+            SpillReader::File(_, ref temp) => temp.path.clone(),
+            SpillReader::Memory(_) => panic!("data was not spilled"),
+        };
+
+        let mut restored = Vec::new();
+        reader.read_to_end(&mut restored).unwrap();
+        assert_eq!(restored, data);
+
+        reader.seek(SeekFrom::Start(5000)).unwrap();
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], data[5000]);
+
+        drop(reader);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn small_data_stays_in_memory() {
+        let mut buffer = SpillBuffer::with_threshold(1000);
+        buffer.write_all(&[1, 2, 3]).unwrap();
+        assert!(!buffer.is_spilled());
+
+        let mut restored = Vec::new();
+        buffer.into_reader().unwrap().read_to_end(&mut restored).unwrap();
+        assert_eq!(restored, [1, 2, 3]);
+    }
+}
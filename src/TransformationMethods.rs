@@ -1,6 +1,7 @@
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Write};
-use std::{result, usize};
+
+use crate::Error::{Error, Result};
 
 // pub const TRANSFORM_BLOCK_SIZE: usize = 256;
 // pub const BWT_RESULT_SIZE: usize = TRANSFORM_BLOCK_SIZE + 1;
@@ -8,8 +9,12 @@ use std::{result, usize};
 pub const TRANSFORM_BLOCK_SIZE: usize = 4096;
 pub const BWT_RESULT_SIZE: usize = TRANSFORM_BLOCK_SIZE + 2;
 
+// Upper bound for a block at any point of a pipeline (64KB), limits memory and time spent
+// by inverse transforms on damaged data
+pub const MAX_TRANSFORMED_BLOCK_SIZE: usize = 1 << 16;
+
 
-fn generate_shifts(input_string: &Vec<u8>) -> Vec<Vec<u8>> {
+fn generate_shifts(input_string: &[u8]) -> Vec<Vec<u8>> {
     let mut shifts = Vec::new();
 
     let length = input_string.len();
@@ -20,13 +25,14 @@ fn generate_shifts(input_string: &Vec<u8>) -> Vec<Vec<u8>> {
     shifts
 }
 
-pub fn BWT(input_string: &Vec<u8>) -> Vec<u8> {
+pub fn BWT(input_string: &[u8]) -> Result<Vec<u8>> {
     // Limited to TRANSFORM_BLOCK_SIZE bytes
     if input_string.len() > TRANSFORM_BLOCK_SIZE {
-        panic!("BWT can only handle inputs of size {} (passed: {})", TRANSFORM_BLOCK_SIZE, input_string.len());
+        return Err(Error::invalid_parameter(format!("BWT can only handle inputs of size {} (passed: {})",
+                                                    TRANSFORM_BLOCK_SIZE, input_string.len())));
     }
 
-    let mut shifts: Vec<Vec<u8>> = generate_shifts(&input_string);
+    let mut shifts: Vec<Vec<u8>> = generate_shifts(input_string);
     shifts.sort_by_key(|x| x.clone());
 
     let mut bwt_result = Vec::new();
@@ -45,24 +51,33 @@ pub fn BWT(input_string: &Vec<u8>) -> Vec<u8> {
         bwt_result.extend_from_slice(&original_index.to_le_bytes());
     }
 
-    bwt_result
+    Ok(bwt_result)
 }
 
-pub fn inverse_BWT(bwt_string: &Vec<u8>) -> Vec<u8> {
+pub fn inverse_BWT(bwt_string: &[u8]) -> Result<Vec<u8>> {
     // Limited to BWT_RESULT_SIZE bytes input (BWT_RESULT_SIZE + BWT_RESULT_SIZE // 8 for original index)
     if bwt_string.len() > BWT_RESULT_SIZE {
-        panic!("BWT inverse can only handle inputs of size {} (passed: {})", BWT_RESULT_SIZE, bwt_string.len());
+        return Err(Error::corrupt(0, format!("BWT inverse can only handle inputs of size {} (passed: {})",
+                                             BWT_RESULT_SIZE, bwt_string.len())));
     }
 
-    let mut length = bwt_string.len() - 1;
-    let mut pos = bwt_string[length] as usize; // Last byte is the original index
+    let index_size = BWT_RESULT_SIZE - TRANSFORM_BLOCK_SIZE;
+    if bwt_string.len() < index_size {
+        return Err(Error::corrupt(0, "BWT block is too short to hold the original index"));
+    }
 
-    if TRANSFORM_BLOCK_SIZE > 256 {
-        length = length - 1;
-        pos = u16::from_le_bytes(bwt_string[length..].try_into().unwrap()) as usize;
+    let length = bwt_string.len() - index_size;
+    let mut pos = if index_size == 1 {
+        bwt_string[length] as usize // Last byte is the original index
+    } else {
+        u16::from_le_bytes([bwt_string[length], bwt_string[length + 1]]) as usize
+    };
+
+    if length > 0 && pos >= length {
+        return Err(Error::corrupt(length as u64, format!("BWT original index {} is out of range (block size: {})", pos, length)));
     }
 
-    let mut enumerated = bwt_string[..length].into_iter().enumerate().collect::<Vec<(usize, &u8)>>();
+    let mut enumerated = bwt_string[..length].iter().enumerate().collect::<Vec<(usize, &u8)>>();
     enumerated.sort_by_key(|&(_, &byte)| byte);
 
     let table: Vec<usize> = enumerated.iter().map(|&(idx, _)| idx).collect();
@@ -74,10 +89,93 @@ pub fn inverse_BWT(bwt_string: &Vec<u8>) -> Vec<u8> {
         result.push(bwt_string[pos]);
     }
 
-    result
+    Ok(result)
+}
+
+// Lyndon factorization (Duval's algorithm), returns (start, length) of non-increasing Lyndon words
+fn lyndon_factorization(input_string: &[u8]) -> Vec<(usize, usize)> {
+    let mut factors = Vec::new();
+    let length = input_string.len();
+    let mut i = 0;
+
+    while i < length {
+        let mut j = i + 1;
+        let mut k = i;
+        while j < length && input_string[k] <= input_string[j] {
+            k = if input_string[k] < input_string[j] { i } else { k + 1 };
+            j += 1;
+        }
+
+        while i <= k {
+            factors.push((i, j - k));
+            i += j - k;
+        }
+    }
+
+    factors
+}
+
+// Bijective BWT: sorts rotations of all Lyndon factors by their infinite periodic extension,
+// so the original string is recovered from cycles and no index has to be stored
+pub fn BWTS(input_string: &[u8]) -> Result<Vec<u8>> {
+    // Limited to TRANSFORM_BLOCK_SIZE bytes
+    if input_string.len() > TRANSFORM_BLOCK_SIZE {
+        return Err(Error::invalid_parameter(format!("BWTS can only handle inputs of size {} (passed: {})",
+                                                    TRANSFORM_BLOCK_SIZE, input_string.len())));
+    }
+
+    // (factor start, factor length, rotation offset)
+    let mut rotations: Vec<(usize, usize, usize)> = Vec::with_capacity(input_string.len());
+    for (start, length) in lyndon_factorization(input_string) {
+        for offset in 0..length {
+            rotations.push((start, length, offset));
+        }
+    }
+
+    // Comparing first len_a + len_b symbols is enough to order two periodic strings
+    rotations.sort_by(|&(start_a, len_a, off_a), &(start_b, len_b, off_b)| {
+        (0..len_a + len_b).map(|k| {
+            input_string[start_a + (off_a + k) % len_a].cmp(&input_string[start_b + (off_b + k) % len_b])
+        })
+        .find(|ord| ord.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    Ok(rotations.iter()
+                .map(|&(start, length, offset)| input_string[start + (offset + length - 1) % length])
+                .collect())
+}
+
+pub fn inverse_BWTS(bwts_string: &[u8]) -> Vec<u8> {
+    let length = bwts_string.len();
+
+    let mut enumerated = bwts_string.iter().enumerate().collect::<Vec<(usize, &u8)>>();
+    enumerated.sort_by_key(|&(_, &byte)| byte);
+
+    let table: Vec<usize> = enumerated.iter().map(|&(idx, _)| idx).collect();
+
+    // Every cycle of the table is a Lyndon word, the smallest rotation of each is met first
+    let mut visited = vec![false; length];
+    let mut words: Vec<Vec<u8>> = Vec::new();
+    for start in 0..length {
+        let mut word = Vec::new();
+        let mut pos = start;
+        while !visited[pos] {
+            visited[pos] = true;
+            pos = table[pos];
+            word.push(bwts_string[pos]);
+        }
+
+        if !word.is_empty() {
+            words.push(word);
+        }
+    }
+
+    // Words were found in increasing order, the factorization is non-increasing
+    words.into_iter().rev().flatten().collect()
 }
 
-pub fn MTF(input_string: &Vec<u8>) -> Vec<u8> {
+pub fn MTF(input_string: &[u8]) -> Vec<u8> {
     let mut symbol_table: Vec<u8> = (0..=255).collect();
     let mut mtf_result = Vec::new();
 
@@ -93,7 +191,7 @@ pub fn MTF(input_string: &Vec<u8>) -> Vec<u8> {
     mtf_result
 }
 
-pub fn inverse_MTF(mtf_string: &Vec<u8>) -> Vec<u8> {
+pub fn inverse_MTF(mtf_string: &[u8]) -> Vec<u8> {
     let mut symbol_table: Vec<u8> = (0..=255).collect();
     let mut result = Vec::new();
 
@@ -108,51 +206,879 @@ pub fn inverse_MTF(mtf_string: &Vec<u8>) -> Vec<u8> {
     result
 }
 
-pub fn perform_BWT_MTF(input_string: &Vec<u8>) -> Vec<u8> {
-    let bwt_result = BWT(input_string);
-    let mtf_result = MTF(&bwt_result);
+// MTF-1: symbols are moved to the second position, only the second one is promoted to the front
+pub fn MTF1(input_string: &[u8]) -> Vec<u8> {
+    let mut symbol_table: Vec<u8> = (0..=255).collect();
+    let mut mtf_result = Vec::with_capacity(input_string.len());
+
+    for &byte in input_string.iter() {
+        let index = symbol_table.iter().position(|&b| b == byte).unwrap();
+        mtf_result.push(index as u8);
+
+        if index != 0 {
+            symbol_table.remove(index);
+            symbol_table.insert(if index == 1 { 0 } else { 1 }, byte);
+        }
+    }
+
+    mtf_result
+}
+
+pub fn inverse_MTF1(mtf_string: &[u8]) -> Vec<u8> {
+    let mut symbol_table: Vec<u8> = (0..=255).collect();
+    let mut result = Vec::with_capacity(mtf_string.len());
+
+    for &index in mtf_string.iter() {
+        let byte = symbol_table[index as usize];
+        result.push(byte);
+
+        if index != 0 {
+            symbol_table.remove(index as usize);
+            symbol_table.insert(if index == 1 { 0 } else { 1 }, byte);
+        }
+    }
+
+    result
+}
+
+// MTF-2: same as MTF-1, but the second symbol is promoted to the front only if the previous symbol was not the front one
+pub fn MTF2(input_string: &[u8]) -> Vec<u8> {
+    let mut symbol_table: Vec<u8> = (0..=255).collect();
+    let mut mtf_result = Vec::with_capacity(input_string.len());
+    let mut prev_index = 0;
+
+    for &byte in input_string.iter() {
+        let index = symbol_table.iter().position(|&b| b == byte).unwrap();
+        mtf_result.push(index as u8);
+
+        if index > 1 || (index == 1 && prev_index != 0) {
+            symbol_table.remove(index);
+            symbol_table.insert(if index == 1 { 0 } else { 1 }, byte);
+        }
+        prev_index = index;
+    }
+
     mtf_result
 }
 
-pub fn perform_inverse_MTF_BWT(mtf_string: &Vec<u8>) -> Vec<u8> {
-    let inverse_mtf_result = inverse_MTF(mtf_string);
-    let inverse_bwt_result = inverse_BWT(&inverse_mtf_result);
-    inverse_bwt_result
+pub fn inverse_MTF2(mtf_string: &[u8]) -> Vec<u8> {
+    let mut symbol_table: Vec<u8> = (0..=255).collect();
+    let mut result = Vec::with_capacity(mtf_string.len());
+    let mut prev_index = 0;
+
+    for &index in mtf_string.iter() {
+        let index = index as usize;
+        let byte = symbol_table[index];
+        result.push(byte);
+
+        if index > 1 || (index == 1 && prev_index != 0) {
+            symbol_table.remove(index);
+            symbol_table.insert(if index == 1 { 0 } else { 1 }, byte);
+        }
+        prev_index = index;
+    }
+
+    result
+}
+
+// Weighted Frequency Count: (last distance of the band, weight of an occurrence in the band)
+const WFC_BANDS: [(usize, u32); 6] = [(1, 4096), (2, 1024), (4, 256), (16, 64), (64, 16), (256, 4)];
+const WFC_WINDOW: usize = 256;
+
+struct WFCState {
+    symbol_table: Vec<u8>,
+    weights: [u32; 256],
+    history: Vec<u8>,
+}
+
+impl WFCState {
+    fn new() -> Self {
+        WFCState {
+            symbol_table: (0..=255).collect(),
+            weights: [0; 256],
+            history: Vec::with_capacity(WFC_WINDOW + 1),
+        }
+    }
+
+    // Account for the symbol coded at the current position and age all previous occurrences by one
+    fn update(&mut self, byte: u8) {
+        if self.history.len() > WFC_WINDOW {
+            self.history.remove(0);
+        }
+        self.history.push(byte);
+
+        let mut changed: Vec<u8> = vec![byte];
+        self.weights[byte as usize] += WFC_BANDS[0].1;
+
+        for (band_id, &(last_distance, weight)) in WFC_BANDS.iter().enumerate() {
+            // Occurrence leaving this band (its distance becomes last_distance + 1)
+            if self.history.len() <= last_distance {
+                break;
+            }
+
+            let symbol = self.history[self.history.len() - 1 - last_distance];
+            let next_weight = WFC_BANDS.get(band_id + 1).map_or(0, |&(_, w)| w);
+            self.weights[symbol as usize] = self.weights[symbol as usize] - weight + next_weight;
+            changed.push(symbol);
+        }
+
+        // Restore ordering by weight (descending) for affected symbols
+        for symbol in changed {
+            let old_pos = self.symbol_table.iter().position(|&b| b == symbol).unwrap();
+            self.symbol_table.remove(old_pos);
+
+            let weight = self.weights[symbol as usize];
+            let new_pos = self.symbol_table.iter()
+                                           .position(|&b| self.weights[b as usize] <= weight)
+                                           .unwrap_or(self.symbol_table.len());
+            self.symbol_table.insert(new_pos, symbol);
+        }
+    }
+}
+
+pub fn WFC(input_string: &[u8]) -> Vec<u8> {
+    let mut state = WFCState::new();
+    let mut wfc_result = Vec::with_capacity(input_string.len());
+
+    for &byte in input_string.iter() {
+        let index = state.symbol_table.iter().position(|&b| b == byte).unwrap();
+        wfc_result.push(index as u8);
+        state.update(byte);
+    }
+
+    wfc_result
+}
+
+pub fn inverse_WFC(wfc_string: &[u8]) -> Vec<u8> {
+    let mut state = WFCState::new();
+    let mut result = Vec::with_capacity(wfc_string.len());
+
+    for &index in wfc_string.iter() {
+        let byte = state.symbol_table[index as usize];
+        result.push(byte);
+        state.update(byte);
+    }
+
+    result
+}
+
+fn push_varint(output: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        output.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+fn read_varint(input: &[u8], pos: &mut usize) -> Result<usize> {
+    let start = *pos;
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = *input.get(*pos).ok_or_else(|| Error::corrupt(*pos as u64, "Unexpected end of block"))?;
+        *pos += 1;
+
+        if shift >= usize::BITS {
+            return Err(Error::corrupt(start as u64, "Variable-length number is too long"));
+        }
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+// Length of the restored block stored in front of IF and DC data
+fn read_block_length(input: &[u8], pos: &mut usize) -> Result<usize> {
+    let length = read_varint(input, pos)?;
+    if length > MAX_TRANSFORMED_BLOCK_SIZE {
+        return Err(Error::corrupt(0, format!("Block length {} exceeds the limit of {} bytes", length, MAX_TRANSFORMED_BLOCK_SIZE)));
+    }
+    Ok(length)
+}
+
+// Presence bitmap of 256 symbols
+fn read_bitmap<'a>(input: &'a [u8], pos: &mut usize) -> Result<&'a [u8]> {
+    let bitmap = input.get(*pos..*pos + 32).ok_or_else(|| Error::corrupt(*pos as u64, "Unexpected end of block"))?;
+    *pos += 32;
+    Ok(bitmap)
+}
+
+// Inversion Frequencies: for every symbol (in increasing order) store the number of greater symbols
+// between its consecutive occurrences. Output: length, presence bitmap, counts, inversion values.
+pub fn IF(input_string: &[u8]) -> Vec<u8> {
+    let mut counts = [0usize; 256];
+    for &byte in input_string.iter() {
+        counts[byte as usize] += 1;
+    }
+
+    let mut if_result = Vec::new();
+    push_varint(&mut if_result, input_string.len());
+
+    let mut bitmap = [0u8; 32];
+    for symbol in (0..256).filter(|&s| counts[s] != 0) {
+        bitmap[symbol / 8] |= 1 << (symbol % 8);
+    }
+    if_result.extend_from_slice(&bitmap);
+
+    for &count in counts.iter().filter(|&&c| c != 0) {
+        push_varint(&mut if_result, count);
+    }
+
+    // Occurrences of the greatest symbol fill all remaining positions, no need to store them
+    let last_symbol = (0..256).rev().find(|&s| counts[s] != 0);
+    for symbol in (0..256).filter(|&s| counts[s] != 0 && Some(s) != last_symbol) {
+        let mut greater_seen = 0;
+        for &byte in input_string.iter() {
+            if byte as usize == symbol {
+                push_varint(&mut if_result, greater_seen);
+                greater_seen = 0;
+            } else if byte as usize > symbol {
+                greater_seen += 1;
+            }
+        }
+    }
+
+    if_result
+}
+
+pub fn inverse_IF(if_string: &[u8]) -> Result<Vec<u8>> {
+    let mut pos = 0;
+    let length = read_block_length(if_string, &mut pos)?;
+    let bitmap = read_bitmap(if_string, &mut pos)?;
+
+    let present: Vec<usize> = (0..256).filter(|&s| bitmap[s / 8] & (1 << (s % 8)) != 0).collect();
+    let counts_pos = pos as u64;
+    let counts: Vec<usize> = present.iter().map(|_| read_varint(if_string, &mut pos)).collect::<Result<_>>()?;
+    if counts.iter().try_fold(0usize, |sum, &count| sum.checked_add(count)) != Some(length) {
+        return Err(Error::corrupt(counts_pos, "Symbol counts do not add up to the block length"));
+    }
+
+    let mut inversions: Vec<Vec<usize>> = Vec::with_capacity(present.len());
+    for &count in counts.iter().take(present.len().saturating_sub(1)) {
+        inversions.push((0..count).map(|_| read_varint(if_string, &mut pos)).collect::<Result<_>>()?);
+    }
+
+    // Rebuild from the greatest symbol down: the current sequence consists only of greater symbols
+    let mut result: Vec<u8> = Vec::with_capacity(length);
+    if let Some((&last_symbol, &last_count)) = present.last().zip(counts.last()) {
+        result.resize(last_count, last_symbol as u8);
+    }
+
+    for (symbol_id, symbol_inversions) in inversions.iter().enumerate().rev() {
+        let symbol = present[symbol_id] as u8;
+        let mut insert_pos = 0;
+
+        for &greater_seen in symbol_inversions.iter() {
+            // Skip greater_seen greater symbols after the previous occurrence
+            let mut skipped = 0;
+            while skipped < greater_seen {
+                let &byte = result.get(insert_pos).ok_or_else(|| Error::corrupt(0, "Inversion frequency points past the end of block"))?;
+                if byte > symbol {
+                    skipped += 1;
+                }
+                insert_pos += 1;
+            }
+
+            result.insert(insert_pos, symbol);
+            insert_pos += 1;
+        }
+    }
+
+    if result.len() != length {
+        return Err(Error::corrupt(0, format!("Inversion frequencies restored {} bytes instead of {}", result.len(), length)));
+    }
+
+    Ok(result)
+}
+
+// Fenwick tree over free (not yet decoded) positions of a Distance Coding block
+struct FreeSlots {
+    tree: Vec<usize>,
+}
+
+impl FreeSlots {
+    fn new(length: usize) -> Self {
+        let mut tree = vec![0; length + 1];
+        for i in 1..=length {
+            tree[i] += 1;
+            let parent = i + (i & i.wrapping_neg());
+            if parent <= length {
+                tree[parent] += tree[i];
+            }
+        }
+        FreeSlots { tree }
+    }
+
+    fn take(&mut self, pos: usize) {
+        let mut i = pos + 1;
+        while i < self.tree.len() {
+            self.tree[i] -= 1;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    // Number of free positions in [0, pos]
+    fn count_up_to(&self, pos: usize) -> usize {
+        let mut i = pos + 1;
+        let mut count = 0;
+        while i > 0 {
+            count += self.tree[i];
+            i -= i & i.wrapping_neg();
+        }
+        count
+    }
+
+    // Position of the n-th (1-based) free slot
+    fn nth_free(&self, mut n: usize) -> usize {
+        let mut pos = 0;
+        let mut step = (self.tree.len() - 1).next_power_of_two();
+        while step > 0 {
+            if pos + step < self.tree.len() && self.tree[pos + step] < n {
+                pos += step;
+                n -= self.tree[pos];
+            }
+            step >>= 1;
+        }
+        pos
+    }
+}
+
+// Distance Coding: store first position of every symbol, then for each position the distance
+// to the next occurrence of the same symbol, counted only over positions not known yet (0 - no more occurrences).
+// All positions before the current one are always known, so the distance is the rank among free positions.
+pub fn DC(input_string: &[u8]) -> Vec<u8> {
+    let length = input_string.len();
+    let mut dc_result = Vec::new();
+    push_varint(&mut dc_result, length);
+
+    let mut next_occurrence: Vec<Option<usize>> = vec![None; length];
+    let mut first_occurrence: [Option<usize>; 256] = [None; 256];
+    for (pos, &byte) in input_string.iter().enumerate().rev() {
+        next_occurrence[pos] = first_occurrence[byte as usize];
+        first_occurrence[byte as usize] = Some(pos);
+    }
+
+    let mut bitmap = [0u8; 32];
+    for symbol in (0..256).filter(|&s| first_occurrence[s].is_some()) {
+        bitmap[symbol / 8] |= 1 << (symbol % 8);
+    }
+    dc_result.extend_from_slice(&bitmap);
+
+    let mut free_slots = FreeSlots::new(length);
+    for &pos in first_occurrence.iter().flatten() {
+        push_varint(&mut dc_result, pos);
+        free_slots.take(pos);
+    }
+
+    for next in next_occurrence {
+        if let Some(next) = next {
+            push_varint(&mut dc_result, free_slots.count_up_to(next));
+            free_slots.take(next);
+        } else {
+            push_varint(&mut dc_result, 0);
+        }
+    }
+
+    dc_result
+}
+
+pub fn inverse_DC(dc_string: &[u8]) -> Result<Vec<u8>> {
+    let mut pos = 0;
+    let length = read_block_length(dc_string, &mut pos)?;
+    let bitmap = read_bitmap(dc_string, &mut pos)?;
+
+    let mut result: Vec<Option<u8>> = vec![None; length];
+    let mut free_slots = FreeSlots::new(length);
+    for symbol in (0..256).filter(|&s| bitmap[s / 8] & (1 << (s % 8)) != 0) {
+        let field_pos = pos as u64;
+        let first = read_varint(dc_string, &mut pos)?;
+        if first >= length || result[first].is_some() {
+            return Err(Error::corrupt(field_pos, format!("Invalid first position {} of symbol {}", first, symbol)));
+        }
+
+        result[first] = Some(symbol as u8);
+        free_slots.take(first);
+    }
+
+    for i in 0..length {
+        let symbol = result[i].ok_or_else(|| Error::corrupt(pos as u64, format!("Position {} left undecoded", i)))?;
+
+        let field_pos = pos as u64;
+        let distance = read_varint(dc_string, &mut pos)?;
+        if distance != 0 {
+            let next = free_slots.nth_free(distance);
+            if next >= length || result[next].is_some() {
+                return Err(Error::corrupt(field_pos, format!("Distance {} points past the end of block", distance)));
+            }
+
+            result[next] = Some(symbol);
+            free_slots.take(next);
+        }
+    }
+
+    Ok(result.into_iter().flatten().collect())
+}
+
+// Byte-wise delta filter: every byte is replaced by its difference with the byte `stride` positions back
+pub fn delta(input_string: &[u8], stride: usize) -> Vec<u8> {
+    let mut delta_result = input_string.to_vec();
+    for i in (stride..input_string.len()).rev() {
+        delta_result[i] = input_string[i].wrapping_sub(input_string[i - stride]);
+    }
+
+    delta_result
+}
+
+pub fn inverse_delta(delta_string: &[u8], stride: usize) -> Vec<u8> {
+    let mut result = delta_string.to_vec();
+    for i in stride..result.len() {
+        result[i] = result[i].wrapping_add(result[i - stride]);
+    }
+
+    result
+}
+
+// Splits interleaved channels (byte i belongs to channel i % channels) into consecutive planes
+pub fn deinterleave(input_string: &[u8], channels: usize) -> Vec<u8> {
+    let mut planes = Vec::with_capacity(input_string.len());
+    for channel in 0..channels {
+        planes.extend(input_string.iter().skip(channel).step_by(channels));
+    }
+
+    planes
+}
+
+pub fn inverse_deinterleave(planes: &[u8], channels: usize) -> Vec<u8> {
+    let mut result = vec![0u8; planes.len()];
+    let mut plane_bytes = planes.iter();
+    for channel in 0..channels {
+        for pos in (channel..planes.len()).step_by(channels) {
+            result[pos] = *plane_bytes.next().unwrap();
+        }
+    }
+
+    result
+}
+
+// x86 branch-call-jump filter: relative targets of E8 (CALL) and E9 (JMP) are converted to absolute ones,
+// so repeated calls of the same function produce identical bytes. Only near targets (high byte 0x00 or 0xFF)
+// are converted and kept in 25-bit signed range, so the decoder makes exactly the same decisions.
+fn BCJ_x86_convert(input_string: &[u8], stream_offset: usize, encoding: bool) -> Vec<u8> {
+    let mut result = input_string.to_vec();
+    let mut i = 0;
+
+    while i + 5 <= result.len() {
+        if result[i] != 0xE8 && result[i] != 0xE9 {
+            i += 1;
+            continue;
+        }
+
+        if result[i + 4] == 0x00 || result[i + 4] == 0xFF {
+            let src = u32::from_le_bytes(result[i + 1..i + 5].try_into().unwrap());
+            let next_instruction = (stream_offset + i + 5) as u32;
+
+            let mut dest = if encoding {
+                src.wrapping_add(next_instruction)
+            } else {
+                src.wrapping_sub(next_instruction)
+            } & 0x01FF_FFFF;
+
+            if dest & 0x0100_0000 != 0 {
+                dest |= 0xFF00_0000;
+            }
+
+            result[i + 1..i + 5].copy_from_slice(&dest.to_le_bytes());
+        }
+
+        // Operand bytes are never treated as opcodes
+        i += 5;
+    }
+
+    result
+}
+
+pub fn BCJ_x86(input_string: &[u8], stream_offset: usize) -> Vec<u8> {
+    BCJ_x86_convert(input_string, stream_offset, true)
+}
+
+pub fn inverse_BCJ_x86(bcj_string: &[u8], stream_offset: usize) -> Vec<u8> {
+    BCJ_x86_convert(bcj_string, stream_offset, false)
+}
+
+pub fn perform_BWT_MTF(input_string: &[u8]) -> Result<Vec<u8>> {
+    Ok(MTF(&BWT(input_string)?))
+}
+
+pub fn perform_inverse_MTF_BWT(mtf_string: &[u8]) -> Result<Vec<u8>> {
+    inverse_BWT(&inverse_MTF(mtf_string))
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransformStage {
+    BWT,
+    BWTS,
+    MTF,
+    MTF1,
+    MTF2,
+    WFC,
+    IF,
+    DC,
+    Delta(u8),          // Stride in bytes (1, 2, 4 or 8)
+    Deinterleave(u8),   // Number of channels
+    BCJ,                // x86 branch-call-jump filter for executables
+}
+
+impl TransformStage {
+    // Serialized stage: (stage id, parameter)
+    pub fn to_bytes(self) -> [u8; 2] {
+        match self {
+            TransformStage::BWT => [1, 0],
+            TransformStage::BWTS => [2, 0],
+            TransformStage::MTF => [3, 0],
+            TransformStage::MTF1 => [4, 0],
+            TransformStage::MTF2 => [5, 0],
+            TransformStage::WFC => [6, 0],
+            TransformStage::IF => [7, 0],
+            TransformStage::DC => [8, 0],
+            TransformStage::Delta(stride) => [9, stride],
+            TransformStage::Deinterleave(channels) => [10, channels],
+            TransformStage::BCJ => [11, 0],
+        }
+    }
+
+    pub fn from_bytes(bytes: [u8; 2]) -> Option<Self> {
+        match bytes {
+            [1, _] => Some(TransformStage::BWT),
+            [2, _] => Some(TransformStage::BWTS),
+            [3, _] => Some(TransformStage::MTF),
+            [4, _] => Some(TransformStage::MTF1),
+            [5, _] => Some(TransformStage::MTF2),
+            [6, _] => Some(TransformStage::WFC),
+            [7, _] => Some(TransformStage::IF),
+            [8, _] => Some(TransformStage::DC),
+            [9, stride] if stride != 0 => Some(TransformStage::Delta(stride)),
+            [10, channels] if channels != 0 => Some(TransformStage::Deinterleave(channels)),
+            [11, _] => Some(TransformStage::BCJ),
+            _ => None,
+        }
+    }
+}
+
+// Predefined pipelines available by transform id
+// Valid preset ids are 0..TRANSFORM_PRESET_COUNT
+pub const TRANSFORM_PRESET_COUNT: u8 = 20;
+
+pub fn transform_pipeline(transform_id: u8) -> Result<Vec<TransformStage>> {
+    use TransformStage::*;
+
+    Ok(match transform_id {
+        0 => vec![],                // No transformation
+        1 => vec![BWT, MTF],        // Both BWT and MTF
+        2 => vec![BWT],             // Only BWT
+        3 => vec![MTF],             // Only MTF
+        4 => vec![BWT, MTF1],       // BWT and MTF-1
+        5 => vec![BWT, MTF2],       // BWT and MTF-2
+        6 => vec![BWT, WFC],        // BWT and Weighted Frequency Count
+        7 => vec![BWT, IF],         // BWT and Inversion Frequencies
+        8 => vec![BWT, DC],         // BWT and Distance Coding
+        9 => vec![BWTS],            // Only bijective BWT
+        10 => vec![BWTS, MTF],      // Bijective BWT and MTF
+        11 => vec![Delta(1)],       // Delta filters for numeric data
+        12 => vec![Delta(2)],
+        13 => vec![Delta(4)],
+        14 => vec![Delta(8)],
+        15 => vec![Delta(2), Deinterleave(2)],  // Delta filters with channels split into planes
+        16 => vec![Delta(4), Deinterleave(4)],
+        17 => vec![Delta(8), Deinterleave(8)],
+        18 => vec![BCJ],            // Only x86 BCJ filter
+        19 => vec![BCJ, BWT, MTF],  // x86 BCJ filter with BWT and MTF
+        _ => return Err(Error::invalid_parameter(format!("Unknown transform: {}", transform_id))),
+    })
+}
+
+pub fn preset_pipelines() -> Vec<Vec<TransformStage>> {
+    (0..TRANSFORM_PRESET_COUNT).map(|transform_id| transform_pipeline(transform_id).unwrap()).collect()
 }
 
-pub fn perform_transform(input_string: &Vec<u8>, transform_id: u8) -> Vec<u8> {
-    if transform_id == 1 {    // Both BWT and MTF
-        return perform_BWT_MTF(input_string);
-    } else if transform_id == 2 {   // Only BWT
-        return BWT(input_string)
-    } else if transform_id == 3 {   // Only MTF
-        return MTF(input_string)
+// Stream offset is the position of the block in the untransformed data (needed for position-dependent filters)
+fn perform_stage(input_string: &[u8], stage: TransformStage, stream_offset: usize) -> Result<Vec<u8>> {
+    Ok(match stage {
+        TransformStage::BWT => BWT(input_string)?,
+        TransformStage::BWTS => BWTS(input_string)?,
+        TransformStage::MTF => MTF(input_string),
+        TransformStage::MTF1 => MTF1(input_string),
+        TransformStage::MTF2 => MTF2(input_string),
+        TransformStage::WFC => WFC(input_string),
+        TransformStage::IF => IF(input_string),
+        TransformStage::DC => DC(input_string),
+        TransformStage::Delta(stride) => delta(input_string, stride as usize),
+        TransformStage::Deinterleave(channels) => deinterleave(input_string, channels as usize),
+        TransformStage::BCJ => BCJ_x86(input_string, stream_offset),
+    })
+}
+
+fn perform_inverse_stage(input_string: &[u8], stage: TransformStage, stream_offset: usize) -> Result<Vec<u8>> {
+    Ok(match stage {
+        TransformStage::BWT => inverse_BWT(input_string)?,
+        TransformStage::BWTS => inverse_BWTS(input_string),
+        TransformStage::MTF => inverse_MTF(input_string),
+        TransformStage::MTF1 => inverse_MTF1(input_string),
+        TransformStage::MTF2 => inverse_MTF2(input_string),
+        TransformStage::WFC => inverse_WFC(input_string),
+        TransformStage::IF => inverse_IF(input_string)?,
+        TransformStage::DC => inverse_DC(input_string)?,
+        TransformStage::Delta(stride) => inverse_delta(input_string, stride as usize),
+        TransformStage::Deinterleave(channels) => inverse_deinterleave(input_string, channels as usize),
+        TransformStage::BCJ => inverse_BCJ_x86(input_string, stream_offset),
+    })
+}
+
+// Results of pipelines with these stages have variable length, so they are prefixed with their size (u32)
+fn is_length_prefixed(pipeline: &[TransformStage]) -> bool {
+    pipeline.iter().any(|stage| matches!(stage, TransformStage::IF | TransformStage::DC))
+}
+
+// Size of the first complete transformed block in the buffer (None if more data is needed)
+pub fn next_transformed_block_size(buffer: &[u8], pipeline: &[TransformStage]) -> Result<Option<usize>> {
+    let block_size = if is_length_prefixed(pipeline) {
+        if buffer.len() < 4 {
+            return Ok(None);
+        }
+
+        let prefix = u32::from_le_bytes(buffer[..4].try_into().unwrap()) as usize;
+        if prefix > MAX_TRANSFORMED_BLOCK_SIZE {
+            return Err(Error::corrupt(0, format!("Block size {} exceeds the limit of {} bytes", prefix, MAX_TRANSFORMED_BLOCK_SIZE)));
+        }
+        4 + prefix
     } else {
-        panic!("Unknown transform: {}", transform_id);
+        TRANSFORM_BLOCK_SIZE + fixed_block_overhead(pipeline)
+    };
+
+    Ok(if buffer.len() >= block_size { Some(block_size) } else { None })
+}
+
+// Upper bound of the transformed data size for the given original size
+pub fn max_transformed_size(original_size: u64, pipeline: &[TransformStage]) -> u64 {
+    let blocks = original_size.div_ceil(TRANSFORM_BLOCK_SIZE as u64);
+    if is_length_prefixed(pipeline) {
+        blocks.saturating_mul(4 + MAX_TRANSFORMED_BLOCK_SIZE as u64)
+    } else {
+        original_size.saturating_add(blocks.saturating_mul(fixed_block_overhead(pipeline) as u64))
+    }
+}
+
+// Every BWT stage adds its original index to a block
+fn fixed_block_overhead(pipeline: &[TransformStage]) -> usize {
+    let bwt_stages = pipeline.iter().filter(|&&stage| stage == TransformStage::BWT).count();
+    bwt_stages * (BWT_RESULT_SIZE - TRANSFORM_BLOCK_SIZE)
+}
+
+pub fn perform_transform(input_string: &[u8], pipeline: &[TransformStage], stream_offset: usize) -> Result<Vec<u8>> {
+    let mut result = input_string.to_vec();
+    for &stage in pipeline {
+        result = perform_stage(&result, stage, stream_offset)?;
+        if result.len() > MAX_TRANSFORMED_BLOCK_SIZE {
+            return Err(Error::invalid_parameter(format!("Pipeline {:?} expands a block beyond {} bytes", pipeline, MAX_TRANSFORMED_BLOCK_SIZE)));
+        }
+    }
+
+    if is_length_prefixed(pipeline) {
+        let mut prefixed = Vec::with_capacity(result.len() + 4);
+        prefixed.extend_from_slice(&(result.len() as u32).to_le_bytes());
+        prefixed.extend(result);
+        result = prefixed;
     }
+
+    Ok(result)
 }
 
-pub fn perform_inverse_transform(input_string: &Vec<u8>, transform_id: u8) -> Vec<u8> {
-    if transform_id == 1 {    // Both BWT and MTF
-        return perform_inverse_MTF_BWT(input_string);
-    } else if transform_id == 2 {   // Only BWT
-        return inverse_BWT(input_string)
-    } else if transform_id == 3 {   // Only MTF
-        return inverse_MTF(input_string)
+// Offsets of corrupt data errors are relative to the passed block
+pub fn perform_inverse_transform(input_string: &[u8], pipeline: &[TransformStage], stream_offset: usize) -> Result<Vec<u8>> {
+    let mut result = if is_length_prefixed(pipeline) {
+        input_string.get(4..).ok_or_else(|| Error::corrupt(0, "Block is too short to hold its size"))?.to_vec()
     } else {
-        panic!("Unknown inverse transform: {}", transform_id);
+        input_string.to_vec()
+    };
+
+    for &stage in pipeline.iter().rev() {
+        result = perform_inverse_stage(&result, stage, stream_offset)?;
     }
+
+    Ok(result)
+}
+
+// Splits a byte stream into TRANSFORM_BLOCK_SIZE blocks and transforms each block once it is complete
+pub struct BlockTransformer {
+    pipeline: Vec<TransformStage>,
+    buffer: Vec<u8>,
+    stream_offset: usize,
 }
 
-pub fn transform_file(input_path: &str, output_path: &str, transform_id: u8) {
-    let mut input_file = BufReader::new(File::open(input_path).expect("Failed to open input file"));
-    let mut output_file = BufWriter::new(File::create(output_path).expect("Failed to create output file"));
+impl BlockTransformer {
+    pub fn new(pipeline: &[TransformStage]) -> Self {
+        BlockTransformer {
+            pipeline: pipeline.to_vec(),
+            buffer: Vec::new(),
+            stream_offset: 0,
+        }
+    }
+
+    // Returns transformed data of all blocks completed by this input
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if self.pipeline.is_empty() {
+            return Ok(data.to_vec());
+        }
+
+        self.buffer.extend_from_slice(data);
+
+        let mut result = Vec::new();
+        while self.buffer.len() >= TRANSFORM_BLOCK_SIZE {
+            let block: Vec<u8> = self.buffer.drain(0..TRANSFORM_BLOCK_SIZE).collect();
+            result.extend(perform_transform(&block, &self.pipeline, self.stream_offset)?);
+            self.stream_offset += block.len();
+        }
+
+        Ok(result)
+    }
+
+    // Transforms the last (partial) block
+    pub fn finish(&mut self) -> Result<Vec<u8>> {
+        if self.buffer.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let block = std::mem::take(&mut self.buffer);
+        self.stream_offset += block.len();
+        perform_transform(&block, &self.pipeline, self.stream_offset - block.len())
+    }
+}
+
+// Restores original data from transformed blocks. Knowing the original size allows to detect
+// the last (partial) block as soon as it is complete, without waiting for the end of input.
+// Streams storing their size after the data pass None and mark the end of input by finish().
+pub struct InverseBlockTransformer {
+    pipeline: Vec<TransformStage>,
+    buffer: Vec<u8>,
+    stream_offset: usize,
+    transformed_offset: u64,    // Position of the buffer start in the transformed data
+    original_size: Option<u64>,
+    last_block_restored: bool,  // Only the last block may restore less than TRANSFORM_BLOCK_SIZE bytes
+}
+
+impl InverseBlockTransformer {
+    pub fn new(pipeline: &[TransformStage], original_size: Option<u64>) -> Self {
+        InverseBlockTransformer {
+            pipeline: pipeline.to_vec(),
+            buffer: Vec::new(),
+            stream_offset: 0,
+            transformed_offset: 0,
+            original_size,
+            last_block_restored: false,
+        }
+    }
+
+    fn next_block_size(&self) -> Result<Option<usize>> {
+        let remaining = self.remaining_size();
+        if remaining == 0 {
+            return Ok(None);
+        }
+
+        if is_length_prefixed(&self.pipeline) {
+            return next_transformed_block_size(&self.buffer, &self.pipeline).map_err(|err| err.at_offset(self.transformed_offset));
+        }
+
+        let block_size = remaining.min(TRANSFORM_BLOCK_SIZE as u64) as usize + fixed_block_overhead(&self.pipeline);
+        Ok(if self.buffer.len() >= block_size { Some(block_size) } else { None })
+    }
+
+    fn remaining_size(&self) -> u64 {
+        match self.original_size {
+            Some(original_size) => original_size.saturating_sub(self.stream_offset as u64),
+            None => u64::MAX,
+        }
+    }
+
+    fn restore_block(&mut self, block_size: usize) -> Result<Vec<u8>> {
+        if self.last_block_restored {
+            return Err(Error::corrupt(self.transformed_offset, "Data follows the last block"));
+        }
+
+        let block: Vec<u8> = self.buffer.drain(0..block_size).collect();
+        let detransformed = perform_inverse_transform(&block, &self.pipeline, self.stream_offset)
+                                .map_err(|err| err.at_offset(self.transformed_offset))?;
+
+        // Every block except the last one restores exactly TRANSFORM_BLOCK_SIZE bytes
+        let expected = self.remaining_size().min(TRANSFORM_BLOCK_SIZE as u64);
+        let size_matches = match self.original_size {
+            Some(_) => detransformed.len() as u64 == expected,
+            None => !detransformed.is_empty() && detransformed.len() <= TRANSFORM_BLOCK_SIZE,
+        };
+        if !size_matches {
+            return Err(Error::corrupt(self.transformed_offset, format!("Block restored to {} bytes instead of {}",
+                                                                      detransformed.len(), expected)));
+        }
+
+        self.last_block_restored = detransformed.len() < TRANSFORM_BLOCK_SIZE;
+        self.stream_offset += detransformed.len();
+        self.transformed_offset += block_size as u64;
+        Ok(detransformed)
+    }
+
+    // Returns restored data of all blocks completed by this input
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if self.pipeline.is_empty() {
+            self.stream_offset += data.len();
+            return Ok(data.to_vec());
+        }
+
+        self.buffer.extend_from_slice(data);
+
+        let mut result = Vec::new();
+        while let Some(block_size) = self.next_block_size()? {
+            result.extend(self.restore_block(block_size)?);
+        }
+
+        Ok(result)
+    }
+
+    // Restores the last (partial) block of a stream of unknown size, the size is known afterwards
+    pub fn finish(&mut self) -> Result<Vec<u8>> {
+        let mut result = Vec::new();
+        if !self.buffer.is_empty() {
+            if is_length_prefixed(&self.pipeline) {
+                return Err(Error::corrupt(self.transformed_offset, "Last block is incomplete"));
+            }
+            result = self.restore_block(self.buffer.len())?;
+        }
+
+        self.original_size = Some(self.restored_size());
+        Ok(result)
+    }
+
+    // Number of restored bytes so far
+    pub fn restored_size(&self) -> u64 {
+        self.stream_offset as u64
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.original_size.is_some_and(|original_size| self.restored_size() >= original_size)
+    }
+}
+
+pub fn transform_file(input_path: &str, output_path: &str, pipeline: &[TransformStage]) -> Result<()> {
+    let mut input_file = BufReader::new(File::open(input_path)?);
+    let mut output_file = BufWriter::new(File::create(output_path)?);
 
     let mut buffer = Vec::new();
-    let mut slice: Vec<u8> = Vec::with_capacity(TRANSFORM_BLOCK_SIZE);
-    slice.resize(TRANSFORM_BLOCK_SIZE, 0);
+    let mut slice: Vec<u8> = vec![0; TRANSFORM_BLOCK_SIZE];
+    let mut stream_offset = 0;
 
-    while let Ok(_bytes_read) = input_file.read(&mut slice) {   // Buffered read for transformation
+    loop {   // Buffered read for transformation
+        let _bytes_read = input_file.read(&mut slice)?;
         if _bytes_read == 0 {
             break;  // EOF
         }
@@ -162,48 +1088,298 @@ pub fn transform_file(input_path: &str, output_path: &str, transform_id: u8) {
         if buffer.len() >= TRANSFORM_BLOCK_SIZE {
             let block: Vec<u8> = buffer.drain(0..TRANSFORM_BLOCK_SIZE).collect();
             
-            let result: Vec<_> = perform_transform(&block, transform_id);
-            output_file.write_all(&result).expect("Failed to write transformed data");
+            let result: Vec<_> = perform_transform(&block, pipeline, stream_offset)?;
+            output_file.write_all(&result)?;
+            stream_offset += block.len();
         }
     }
 
-    if buffer.len() > 0 {
-        let result: Vec<_> = perform_BWT_MTF(&buffer);
-        output_file.write_all(&result).expect("Failed to write transformed data");
+    if !buffer.is_empty() {
+        let result: Vec<_> = perform_transform(&buffer, pipeline, stream_offset)?;
+        output_file.write_all(&result)?;
     }
+
+    output_file.flush()?;
+    Ok(())
 }
 
-pub fn inverse_transform_file(input_path: &str, output_path: &str, transform_id: u8) {
-    let mut input_file = BufReader::new(File::open(input_path).expect("Failed to open input file"));
-    let mut output_file = BufWriter::new(File::create(output_path).expect("Failed to create output file"));
+pub fn inverse_transform_file(input_path: &str, output_path: &str, pipeline: &[TransformStage]) -> Result<()> {
+    let mut input_file = BufReader::new(File::open(input_path)?);
+    let mut output_file = BufWriter::new(File::create(output_path)?);
 
     let mut buffer = Vec::new();
-    
-    let transform_block_size = if transform_id == 1 {
-        BWT_RESULT_SIZE
-    } else {
-        TRANSFORM_BLOCK_SIZE
-    };
-
-    let mut slice: Vec<u8> = Vec::with_capacity(transform_block_size);
-    slice.resize(transform_block_size, 0);
+    let mut slice: Vec<u8> = vec![0; BWT_RESULT_SIZE];
+    let mut stream_offset = 0;
+    let mut transformed_offset = 0;
 
-    while let Ok(_bytes_read) = input_file.read(&mut slice) {   // Buffered read for inverse transformation
+    loop {   // Buffered read for inverse transformation
+        let _bytes_read = input_file.read(&mut slice)?;
         if _bytes_read == 0 {
             break;  // EOF
         }
 
         buffer.extend_from_slice(&slice[.._bytes_read]);
 
-        while buffer.len() >= transform_block_size {
-            let block: Vec<u8> = buffer.drain(0..transform_block_size).collect();
-            let detransformed = perform_inverse_transform(&block, transform_id);
-            output_file.write_all(&detransformed).expect("Failed to write inversed data");
+        while let Some(block_size) = next_transformed_block_size(&buffer, pipeline).map_err(|err| err.at_offset(transformed_offset))? {
+            let block: Vec<u8> = buffer.drain(0..block_size).collect();
+            let detransformed = perform_inverse_transform(&block, pipeline, stream_offset)
+                                    .map_err(|err| err.at_offset(transformed_offset))?;
+            output_file.write_all(&detransformed)?;
+            stream_offset += detransformed.len();
+            transformed_offset += block_size as u64;
         }
     }
 
-    if buffer.len() > 0 {
-        let detransformed = perform_inverse_MTF_BWT(&buffer);
-        output_file.write_all(&detransformed).expect("Failed to write inversed data");
+    if !buffer.is_empty() {
+        let detransformed = perform_inverse_transform(&buffer, pipeline, stream_offset)
+                                .map_err(|err| err.at_offset(transformed_offset))?;
+        output_file.write_all(&detransformed)?;
     }
-}
\ No newline at end of file
+
+    output_file.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_blocks() -> Vec<Vec<u8>> {
+        vec![
+            b"banana".to_vec(),
+            b"abab".to_vec(),
+            b"mississippi river, mississippi state".to_vec(),
+            vec![42; 100],
+            (0..TRANSFORM_BLOCK_SIZE).map(|i| (i * i % 251) as u8).collect(),
+        ]
+    }
+
+    #[test]
+    fn BWTS_round_trip() {
+        for block in sample_blocks() {
+            assert_eq!(inverse_BWTS(&BWTS(&block).unwrap()), block);
+        }
+    }
+
+    #[test]
+    fn BWTS_has_no_index_overhead() {
+        for block in sample_blocks() {
+            assert_eq!(BWTS(&block).unwrap().len(), block.len());
+            assert_eq!(BWT(&block).unwrap().len(), block.len() + 2);
+        }
+    }
+
+    #[test]
+    fn BWTS_decodes_same_as_BWT() {
+        for block in sample_blocks() {
+            for (bwts_id, bwt_id) in [(9, 2), (10, 1)] {
+                let (bwts_pipeline, bwt_pipeline) = (transform_pipeline(bwts_id).unwrap(), transform_pipeline(bwt_id).unwrap());
+                let from_bwts = perform_inverse_transform(&perform_transform(&block, &bwts_pipeline, 0).unwrap(), &bwts_pipeline, 0).unwrap();
+                let from_bwt = perform_inverse_transform(&perform_transform(&block, &bwt_pipeline, 0).unwrap(), &bwt_pipeline, 0).unwrap();
+                assert_eq!(from_bwts, from_bwt);
+                assert_eq!(from_bwts, block);
+            }
+        }
+    }
+
+    #[test]
+    fn second_stage_transforms_round_trip() {
+        for block in sample_blocks().into_iter().chain([Vec::new(), vec![7]]) {
+            assert_eq!(inverse_MTF1(&MTF1(&block)), block);
+            assert_eq!(inverse_MTF2(&MTF2(&block)), block);
+            assert_eq!(inverse_WFC(&WFC(&block)), block);
+            assert_eq!(inverse_IF(&IF(&block)).unwrap(), block);
+        }
+    }
+
+    #[test]
+    fn MTF1_and_MTF2_move_to_second_position() {
+        // Symbol from position 2 goes to position 1 and reaches the front only on its next use
+        assert_eq!(MTF(&[2, 2, 2]), [2, 0, 0]);
+        assert_eq!(MTF1(&[2, 2, 2]), [2, 1, 0]);
+        assert_eq!(MTF2(&[2, 2, 2]), [2, 1, 0]);
+
+        // MTF-2 leaves the symbol at position 1 unmoved right after the front symbol was coded
+        assert_eq!(MTF1(&[0, 1, 1]), [0, 1, 0]);
+        assert_eq!(MTF2(&[0, 1, 1]), [0, 1, 1]);
+    }
+
+    #[test]
+    fn WFC_ranks_recent_symbols_first() {
+        assert_eq!(WFC(b"aa"), [b'a', 0]);
+        // 'b' outweighs the older 'a', which stays second
+        assert_eq!(WFC(b"aba"), [b'a', b'b', 1]);
+    }
+
+    #[test]
+    fn IF_stores_counts_and_inversions() {
+        let mut expected = vec![4];
+        let mut bitmap = [0u8; 32];
+        bitmap[(b'a' / 8) as usize] = 0b110;
+        expected.extend_from_slice(&bitmap);
+        // Counts of 'a' and 'b', then 'b's seen before each 'a'
+        expected.extend_from_slice(&[2, 2, 0, 1]);
+        assert_eq!(IF(b"abab"), expected);
+    }
+
+    #[test]
+    fn inverse_IF_rejects_truncated_varint() {
+        let mut block = vec![b'a'];
+        block.extend_from_slice(&[b'b'; 200]);
+        block.push(b'a');
+
+        // Last inversion value (200) takes two bytes, keep only the first one
+        let encoded = IF(&block);
+        assert_eq!(encoded[encoded.len() - 2..], [0xC8, 0x01]);
+        let truncated = &encoded[..encoded.len() - 1];
+        assert!(matches!(inverse_IF(truncated), Err(Error::CorruptData { offset, .. }) if offset == truncated.len() as u64));
+    }
+
+    fn DC_header_of_banana_BWT() -> Vec<u8> {
+        let mut expected = vec![6];
+        let mut bitmap = [0u8; 32];
+        bitmap[(b'a' / 8) as usize] = 0b110;
+        bitmap[(b'n' / 8) as usize] = 1 << (b'n' % 8);
+        expected.extend_from_slice(&bitmap);
+        expected
+    }
+
+    #[test]
+    fn DC_encodes_distances_over_unknown_positions() {
+        let bwt = &BWT(b"banana").unwrap()[..6];
+        assert_eq!(bwt, b"nnbaaa");
+
+        // First positions of 'a', 'b', 'n', then per position the rank of the next occurrence among free slots
+        let mut expected = DC_header_of_banana_BWT();
+        expected.extend_from_slice(&[3, 2, 0, 1, 0, 0, 1, 1, 0]);
+        assert_eq!(DC(bwt), expected);
+        assert_eq!(inverse_DC(&expected).unwrap(), bwt);
+    }
+
+    #[test]
+    fn inverse_DC_rejects_invalid_fields() {
+        let encoded = DC(b"nnbaaa");
+        let distances_pos = DC_header_of_banana_BWT().len() + 3;
+
+        // Only three positions are left free after the first occurrences
+        let mut out_of_range = encoded.clone();
+        out_of_range[distances_pos] = 4;
+        assert!(matches!(inverse_DC(&out_of_range), Err(Error::CorruptData { offset, .. }) if offset == distances_pos as u64));
+
+        // First position of 'a' past a shortened block
+        let mut too_short = encoded.clone();
+        too_short[0] = 2;
+        assert!(inverse_DC(&too_short).is_err());
+
+        // Longer block leaves a position without a symbol
+        let mut too_long = encoded.clone();
+        too_long[0] = 7;
+        assert!(inverse_DC(&too_long).is_err());
+
+        let mut over_limit = Vec::new();
+        push_varint(&mut over_limit, MAX_TRANSFORMED_BLOCK_SIZE + 1);
+        over_limit.extend_from_slice(&encoded[1..]);
+        assert!(inverse_DC(&over_limit).is_err());
+
+        assert!(inverse_DC(&[0x86]).is_err());
+        assert!(inverse_DC(&encoded[..1]).is_err());
+    }
+
+    #[test]
+    fn delta_of_little_endian_integers() {
+        let words: Vec<u8> = [1000u16, 1001, 1003].iter().flat_map(|w| w.to_le_bytes()).collect();
+        assert_eq!(delta(&words, 2), [0xE8, 0x03, 1, 0, 2, 0]);
+        assert_eq!(inverse_delta(&delta(&words, 2), 2), words);
+
+        let dwords: Vec<u8> = [0x0102_0304u32, 0x0102_0305, 0x0102_0300].iter().flat_map(|d| d.to_le_bytes()).collect();
+        assert_eq!(delta(&dwords, 4), [4, 3, 2, 1, 1, 0, 0, 0, 0xFB, 0, 0, 0]);
+        assert_eq!(inverse_delta(&delta(&dwords, 4), 4), dwords);
+    }
+
+    #[test]
+    fn delta_and_deinterleave_partial_strides() {
+        let bytes = [1, 2, 3, 4, 5, 7];
+        assert_eq!(delta(&bytes, 4), [1, 2, 3, 4, 4, 5]);
+        assert_eq!(inverse_delta(&delta(&bytes, 4), 4), bytes);
+        assert_eq!(delta(&bytes[..3], 4), bytes[..3]);
+
+        // Channels past the end of the last sample get one byte less
+        assert_eq!(deinterleave(&[0, 1, 2, 3, 4, 5], 4), [0, 4, 1, 5, 2, 3]);
+        assert_eq!(deinterleave(&[0, 1, 2], 4), [0, 1, 2]);
+    }
+
+    #[test]
+    fn inverse_deinterleave_short_final_block() {
+        assert_eq!(inverse_deinterleave(&[0, 4, 1, 5, 2, 3], 4), [0, 1, 2, 3, 4, 5]);
+        assert_eq!(inverse_deinterleave(&[0, 1, 2], 4), [0, 1, 2]);
+
+        // Last block of the stream is shorter than a transform block and not a multiple of the channel count
+        let pipeline = transform_pipeline(17).unwrap();
+        let data: Vec<u8> = (0..TRANSFORM_BLOCK_SIZE + 13).map(|i| (i * 7 % 256) as u8).collect();
+        for block in data.chunks(TRANSFORM_BLOCK_SIZE) {
+            let transformed = perform_transform(block, &pipeline, 0).unwrap();
+            assert_eq!(perform_inverse_transform(&transformed, &pipeline, 0).unwrap(), block);
+        }
+    }
+
+    // CALL at offset 16 followed by a backward JMP at offset 32
+    fn BCJ_sample() -> Vec<u8> {
+        let mut code = vec![0x90; 48];
+        code[16..21].copy_from_slice(&[0xE8, 0x00, 0x01, 0x00, 0x00]);
+        code[32..37].copy_from_slice(&[0xE9, 0xF0, 0xFF, 0xFF, 0xFF]);
+        code
+    }
+
+    #[test]
+    fn BCJ_converts_relative_targets_to_absolute() {
+        let converted = BCJ_x86(&BCJ_sample(), 0);
+        // 0x100 + next instruction at 21, -0x10 + next instruction at 37
+        assert_eq!(converted[16..21], [0xE8, 0x15, 0x01, 0x00, 0x00]);
+        assert_eq!(converted[32..37], [0xE9, 0x15, 0x00, 0x00, 0x00]);
+        assert_eq!(inverse_BCJ_x86(&converted, 0), BCJ_sample());
+    }
+
+    #[test]
+    fn BCJ_honours_stream_offset() {
+        let converted = BCJ_x86(&BCJ_sample(), 0x1000);
+        assert_eq!(converted[16..21], [0xE8, 0x15, 0x11, 0x00, 0x00]);
+        assert_eq!(inverse_BCJ_x86(&converted, 0x1000), BCJ_sample());
+        assert_ne!(inverse_BCJ_x86(&converted, 0), BCJ_sample());
+    }
+
+    #[test]
+    fn BCJ_leaves_far_and_split_operands() {
+        // High byte of the operand is neither 0x00 nor 0xFF
+        let far_call = [0xE8, 0x10, 0x00, 0x00, 0x12, 0x90];
+        assert_eq!(BCJ_x86(&far_call, 0), far_call);
+
+        // CALL straddling the edge of a transform block keeps its operand bytes in both blocks
+        let pipeline = transform_pipeline(18).unwrap();
+        let mut code = vec![0x90; 2 * TRANSFORM_BLOCK_SIZE];
+        code[TRANSFORM_BLOCK_SIZE - 3..TRANSFORM_BLOCK_SIZE + 2].copy_from_slice(&[0xE8, 0x00, 0x01, 0x00, 0x00]);
+        for (block_id, block) in code.chunks(TRANSFORM_BLOCK_SIZE).enumerate() {
+            let stream_offset = block_id * TRANSFORM_BLOCK_SIZE;
+            let transformed = perform_transform(block, &pipeline, stream_offset).unwrap();
+            assert_eq!(transformed, block);
+            assert_eq!(perform_inverse_transform(&transformed, &pipeline, stream_offset).unwrap(), block);
+        }
+    }
+
+    #[test]
+    fn corrupted_blocks_return_errors() {
+        let mut bwt = BWT(b"banana").unwrap();
+        let length = bwt.len();
+        bwt[length - 2..].copy_from_slice(&100u16.to_le_bytes());
+        assert!(matches!(inverse_BWT(&bwt), Err(Error::CorruptData { offset: 6, .. })));
+        assert!(inverse_BWT(&[1]).is_err());
+
+        let dc = DC(b"mississippi");
+        assert!(inverse_DC(&dc[..dc.len() - 3]).is_err());
+        assert!(inverse_DC(&[5, 0xFF]).is_err());
+        assert!(inverse_IF(&[0x80, 0x80]).is_err());
+
+        let pipeline = transform_pipeline(7).unwrap();
+        assert!(perform_inverse_transform(&[1, 0], &pipeline, 0).is_err());
+        assert!(matches!(transform_pipeline(TRANSFORM_PRESET_COUNT), Err(Error::InvalidParameter(_))));
+    }
+}
@@ -0,0 +1,60 @@
+//! Block-sorting compression library: Huffman and LZW codecs on top of a configurable pipeline of
+//! reversible transforms (BWT, move-to-front variants, delta and x86 filters).
+//!
+//! Every compressed stream starts with a container header naming its codec and transforms, so a
+//! single decoder entry point handles all of them:
+//!
+//! ```
+//! use Lab5::Checksum::{ChecksumKind, ChecksumSettings};
+//! use Lab5::Container::{self, CODEC_LZW};
+//! use Lab5::TransformationMethods::transform_pipeline;
+//!
+//! let data = b"mississippi river, mississippi state".repeat(10);
+//! let pipeline = transform_pipeline(1)?;  // BWT and MTF
+//! let checksum = ChecksumSettings::new(ChecksumKind::CRC32, true);
+//!
+//! let compressed = Container::compress(&data, CODEC_LZW, &pipeline, checksum)?;
+//! assert_eq!(Container::decompress(&compressed)?, data);
+//! # Ok::<(), Lab5::Error::Error>(())
+//! ```
+//!
+//! Large inputs are better streamed through [`Container::encode_stream`] and
+//! [`Container::open_decoder`], or split into independently decodable blocks by [`Seekable`].
+//!
+//! # Features
+//!
+//! Each codec is behind a feature of its own, both enabled by default:
+//!
+//! - `huffman`: [`Huffman`] codec
+//! - `lzw`: [`LZWCoderEnhanced`] codec
+//!
+//! Streams of codecs left out of the build are rejected with [`Error::Error::InvalidParameter`],
+//! [`Codec::registered_codecs`] lists the available ones.
+#![allow(non_snake_case)]
+
+/// Bit-level readers and writers and variable-length integer codes
+pub mod BitStream;
+/// Reversible block transforms and pipelines of them
+pub mod TransformationMethods;
+/// Huffman codec
+#[cfg(feature = "huffman")]
+pub mod Huffman;
+/// LZW codec with variable-width codes
+#[cfg(feature = "lzw")]
+pub mod LZWCoderEnhanced;
+/// Common interface of the codecs and their registry
+pub mod Codec;
+/// Stream header, codec selection and the single decoding entry point
+pub mod Container;
+/// Codec and pipeline selection by trial compression of input samples
+pub mod MethodSelection;
+/// Checksums of the original data stored in compressed streams
+pub mod Checksum;
+/// Streams split into independently compressed blocks with random access
+pub mod Seekable;
+/// Multi-file archives
+pub mod Archive;
+/// Error type of the library
+pub mod Error;
+/// Memory buffer moving to a temporary file when it grows large
+pub mod SpillBuffer;